@@ -1,18 +1,39 @@
 use crate::{
+    formatter::format_script,
     game::{Chunk, Collider, Color, Direction, Game, Kind, Part},
-    lexer, parser,
-    transpiler::transpile_game,
+    interpreter::{
+        coverage::Report,
+        debug::{Debugger, Stopped},
+        testing::{discover_tests, run_test, test_prefabs, AssertionFailed},
+        Interpreter,
+    },
+    lsp,
+    parser::{
+        self,
+        grammar::{Span, Statement},
+        SyntaxError,
+    },
+    transpiler::{
+        budget::{check_budget, Limits},
+        build_graph,
+        dot::write_dot,
+        prefabs::get_prefabs,
+        raise::raise_level,
+        transpile_game, Warning,
+    },
 };
-use anyhow::Result;
-use clap::{Parser, Subcommand, ValueEnum};
+use anyhow::{anyhow, Result};
+use clap::{Args as ClapArgs, Parser, Subcommand, ValueEnum};
 use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use itertools::Itertools;
 use ndarray::{s, Array3, Array4};
 use std::{
+    collections::HashSet,
     fmt::Debug,
     fs::File,
-    io::{stdout, BufReader, BufWriter, Read, Write},
+    io::{stdin, stdout, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
 };
-use winnow::{combinator::terminated, stream::TokenSlice, Parser as _};
 
 /// Simple program to greet a person
 #[derive(Parser, Debug)]
@@ -26,6 +47,8 @@ pub struct Args {
 #[derive(Subcommand, Debug)]
 pub enum Command {
     /// Compile a script
+    #[clap(after_help = "The default limits are guesses, as Fancade doesn't publish its own. \
+                         Override them with the --max-* flags if the app disagrees.")]
     Build {
         /// Path of the script to compile
         #[clap()]
@@ -38,6 +61,9 @@ pub enum Command {
         /// How to encode the output
         #[clap(short, long, default_value_t, value_enum)]
         encoding: Encoding,
+
+        #[command(flatten)]
+        budget: Budget,
     },
 
     /// Load a game binary
@@ -59,6 +85,85 @@ pub enum Command {
         decoding: Decoding,
     },
 
+    /// Run a script or the first level of a game binary without Fancade
+    Run {
+        /// Path of the script or game to run
+        #[clap()]
+        path: String,
+
+        /// Read the path as a game binary with the given decoding instead of a script
+        #[clap(short, long, value_enum)]
+        decoding: Option<Decoding>,
+
+        /// Number of frames to simulate
+        #[clap(short, long, default_value_t = 1)]
+        frames: u64,
+
+        /// Print the prefabs executed in each frame
+        #[clap(short, long)]
+        trace: bool,
+
+        /// Seed of the generator behind `random`
+        #[clap(short, long, default_value_t = 0)]
+        seed: u64,
+
+        /// Print how often each line of the script ran
+        #[clap(short, long)]
+        coverage: bool,
+    },
+
+    /// Step through a script without Fancade
+    Debug {
+        /// Path of the script to debug
+        #[clap()]
+        path: String,
+
+        /// Lines to stop on, stepping from the start if there are none
+        #[clap(short, long = "break")]
+        breakpoints: Vec<usize>,
+
+        /// Number of frames to simulate
+        #[clap(short, long, default_value_t = 1)]
+        frames: u64,
+
+        /// Seed of the generator behind `random`
+        #[clap(short, long, default_value_t = 0)]
+        seed: u64,
+    },
+
+    /// Run the `test_*` definitions of scripts without Fancade
+    Test {
+        /// Scripts or directories containing scripts to test
+        #[clap(default_value = ".")]
+        paths: Vec<String>,
+
+        /// Number of frames to simulate per test
+        #[clap(short, long, default_value_t = 1)]
+        frames: u64,
+
+        /// Seed of the generator behind `random`
+        #[clap(short, long, default_value_t = 0)]
+        seed: u64,
+
+        /// Print how often each line of the script ran
+        #[clap(short, long)]
+        coverage: bool,
+    },
+
+    /// Reprint scripts in canonical style
+    Fmt {
+        /// Scripts or directories containing scripts to format
+        #[clap(default_value = ".")]
+        paths: Vec<String>,
+
+        /// Only report scripts that aren't formatted instead of rewriting them
+        #[clap(long)]
+        check: bool,
+    },
+
+    /// Serve the language server over stdio
+    Lsp,
+
     Generate {
         /// Where to store the output
         #[clap(short, long)]
@@ -70,12 +175,71 @@ pub enum Command {
     },
 }
 
+#[derive(ClapArgs, Debug)]
+pub struct Budget {
+    /// Maximum number of occupied block cells per level, counting each cell of larger blocks
+    #[clap(long, default_value_t = Limits::default().blocks)]
+    max_blocks: usize,
+
+    /// Maximum number of wires per level
+    #[clap(long, default_value_t = Limits::default().wires)]
+    max_wires: usize,
+
+    /// Maximum number of opts per level
+    #[clap(long, default_value_t = Limits::default().opts)]
+    max_opts: usize,
+
+    /// Maximum level size along the x axis
+    #[clap(long, default_value_t = Limits::default().size[0])]
+    max_size_x: usize,
+
+    /// Maximum level size along the y axis
+    #[clap(long, default_value_t = Limits::default().size[1])]
+    max_size_y: usize,
+
+    /// Maximum level size along the z axis
+    #[clap(long, default_value_t = Limits::default().size[2])]
+    max_size_z: usize,
+
+    /// Maximum number of custom chunks
+    #[clap(long, default_value_t = Limits::default().chunks)]
+    max_chunks: usize,
+
+    /// What to do when a limit is exceeded
+    #[clap(long, default_value_t, value_enum)]
+    over_budget: OverBudget,
+
+    /// Print the usage of every limit, not only the exceeded ones
+    #[clap(short, long)]
+    verbose: bool,
+}
+
+impl Budget {
+    fn limits(&self) -> Limits {
+        Limits {
+            blocks: self.max_blocks,
+            wires: self.max_wires,
+            opts: self.max_opts,
+            size: [self.max_size_x, self.max_size_y, self.max_size_z],
+            chunks: self.max_chunks,
+        }
+    }
+}
+
+#[derive(ValueEnum, Default, Clone, Debug)]
+pub enum OverBudget {
+    #[default]
+    Deny,
+    Warn,
+}
+
 #[derive(ValueEnum, Default, Clone, Debug)]
 pub enum Encoding {
     #[default]
     Zlib,
     Raw,
     Debug,
+    Dot,
 }
 
 #[derive(ValueEnum, Default, Clone, Debug)]
@@ -93,36 +257,23 @@ pub fn run() -> Result<()> {
             path,
             out,
             encoding,
+            budget,
         } => {
             let content = std::fs::read_to_string(&path)?;
             let content = content.as_str();
-            let tokens = lexer::tokens.parse(content);
-
-            let Ok(tokens) = tokens else {
-                match tokens {
-                    Ok(tokens) => println!("{:#?}", tokens),
-                    Err(error) => {
-                        let message = error.inner().to_string();
-                        let input = (*error.input()).to_owned();
-                        let span = error.char_span();
-                        let message = annotate_snippets::Level::Error.title(&message).snippet(
-                            annotate_snippets::Snippet::source(&input)
-                                .fold(true)
-                                .annotation(annotate_snippets::Level::Error.span(span.clone())),
-                        );
-                        let renderer = annotate_snippets::Renderer::plain();
-                        let rendered = renderer.render(message);
-                        panic!("{}", rendered);
-                    }
-                };
-                return Ok(());
-            };
-
-            let grammar = terminated(parser::statements0, lexer::token::Kind::EndOfFile)
-                .parse(TokenSlice::new(&tokens))
-                .unwrap();
+            let grammar = parse_script(&path, content)?;
 
-            let game = transpile_game(grammar)?;
+            let mut warnings = Vec::new();
+            let game = transpile_game(grammar, &mut warnings)?;
+            for warning in warnings.iter() {
+                eprintln!("{}", render_warning(&path, content, warning));
+            }
+            check_budget(
+                &game,
+                &budget.limits(),
+                matches!(budget.over_budget, OverBudget::Deny),
+                budget.verbose,
+            )?;
 
             let writer: Box<dyn Write> = match out {
                 Some(out) => Box::new(File::create_new(out)?),
@@ -138,18 +289,7 @@ pub fn run() -> Result<()> {
             encoding,
             decoding,
         } => {
-            let file = File::open(&path)?;
-            let reader: Box<dyn Read> = match decoding {
-                Decoding::Zlib => Box::new(ZlibDecoder::new(file)),
-                Decoding::Raw => Box::new(file),
-            };
-            let mut reader = BufReader::new(reader);
-
-            let game = Game::read(&mut reader)?;
-
-            let Ok(0) = reader.read(&mut [0; 1]) else {
-                panic!("not all game data could be read!");
-            };
+            let game = read_game(&path, decoding)?;
 
             let writer: Box<dyn Write> = match out {
                 Some(out) => Box::new(File::create_new(out)?),
@@ -159,6 +299,198 @@ pub fn run() -> Result<()> {
             write_game_with_encoding(&mut writer, game, encoding)?;
         }
 
+        Command::Run {
+            path,
+            decoding,
+            frames,
+            trace,
+            seed,
+            coverage,
+        } => {
+            let prefabs = get_prefabs();
+            let content = match decoding {
+                Some(_) if coverage => return Err(anyhow!("Coverage can only be reported for scripts!")),
+                Some(_) => String::new(),
+                None => std::fs::read_to_string(&path)?,
+            };
+            let mut report = None;
+
+            let graph = match decoding {
+                Some(decoding) => {
+                    let game = read_game(&path, decoding)?;
+                    let level = game
+                        .chunks
+                        .iter()
+                        .find(|chunk| matches!(chunk.kind, Kind::Level))
+                        .ok_or_else(|| anyhow!("Game has no levels!"))?;
+                    raise_level(level, &prefabs)?
+                }
+                None => {
+                    let grammar = parse_script(&path, &content)?;
+                    if coverage {
+                        report = Some(Report::new(&content, &grammar));
+                    }
+
+                    let mut warnings = Vec::new();
+                    let graph = build_graph(grammar, &prefabs, &mut warnings)?;
+                    for warning in warnings.iter() {
+                        eprintln!("{}", render_warning(&path, &content, warning));
+                    }
+                    graph
+                }
+            };
+
+            let mut interpreter = Interpreter::new(&graph, &prefabs);
+            interpreter.set_seed(seed);
+            for _ in 0..frames {
+                let frame = interpreter.current_frame();
+                let fired = interpreter.run_frame()?;
+                if trace {
+                    let fired = fired
+                        .iter()
+                        .map(|&node| format!("{}#{}", graph.nodes[node].prefab, node))
+                        .join(" ");
+                    eprintln!("frame {}: {}", frame, fired);
+                }
+            }
+
+            if let Some(mut report) = report {
+                report.record(&graph, &prefabs, interpreter.hits());
+                print!("{}", report.render());
+            }
+        }
+
+        Command::Debug {
+            path,
+            breakpoints,
+            frames,
+            seed,
+        } => {
+            let prefabs = get_prefabs();
+            let content = std::fs::read_to_string(&path)?;
+            let content = content.as_str();
+            let grammar = parse_script(&path, content)?;
+
+            let mut warnings = Vec::new();
+            let graph = build_graph(grammar, &prefabs, &mut warnings)?;
+            for warning in warnings.iter() {
+                eprintln!("{}", render_warning(&path, content, warning));
+            }
+
+            let mut interpreter = Interpreter::new(&graph, &prefabs);
+            interpreter.set_seed(seed);
+            interpreter.set_hook(Box::new(Debugger::new(content, breakpoints, stdin().lock(), stdout())));
+            for _ in 0..frames {
+                match interpreter.run_frame() {
+                    Ok(_) => {}
+                    Err(error) if error.is::<Stopped>() => return Ok(()),
+                    Err(error) => return Err(error),
+                }
+            }
+        }
+
+        Command::Test {
+            paths,
+            frames,
+            seed,
+            coverage,
+        } => {
+            let mut prefabs = get_prefabs();
+            for prefab in test_prefabs() {
+                prefabs.insert(prefab.name.clone(), prefab);
+            }
+
+            let mut scripts = Vec::new();
+            for path in paths.iter() {
+                discover_scripts(Path::new(path), &mut scripts)?;
+            }
+
+            let (mut passed, mut failed) = (0, 0);
+            let mut reported = HashSet::new();
+            for path in scripts {
+                let path = path.to_string_lossy().to_string();
+                let content = std::fs::read_to_string(&path)?;
+                let content = content.as_str();
+                let statements = parse_script(&path, content)?;
+                let mut report = Report::new(content, &statements);
+                let (tests, script) = discover_tests(statements);
+
+                for test in tests {
+                    let Statement::Definition { name, statements, .. } = test else {
+                        unreachable!();
+                    };
+
+                    let mut warnings = Vec::new();
+                    let result = run_test(statements, &script, &prefabs, frames, seed, &mut report, &mut warnings);
+                    for warning in warnings.iter() {
+                        let rendered = render_warning(&path, content, warning);
+                        if reported.insert(rendered.clone()) {
+                            eprintln!("{}", rendered);
+                        }
+                    }
+
+                    match result {
+                        Ok(()) => {
+                            println!("test {}::{} ... ok", path, name);
+                            passed += 1;
+                        }
+                        Err(error) => {
+                            println!("test {}::{} ... FAILED", path, name);
+                            let span = error.downcast_ref::<AssertionFailed>().and_then(|failed| failed.span.clone());
+                            let message = error.to_string();
+                            println!(
+                                "{}",
+                                render_diagnostic(annotate_snippets::Level::Error, &path, content, &message, span)
+                            );
+                            failed += 1;
+                        }
+                    }
+                }
+
+                if coverage {
+                    println!("coverage of {}:", path);
+                    print!("{}", report.render());
+                }
+            }
+
+            println!("test result: {} passed; {} failed", passed, failed);
+            if failed > 0 {
+                return Err(anyhow!("{} test(s) failed!", failed));
+            }
+        }
+
+        Command::Fmt { paths, check } => {
+            let mut scripts = Vec::new();
+            for path in paths.iter() {
+                discover_scripts(Path::new(path), &mut scripts)?;
+            }
+
+            let mut unformatted = 0;
+            for path in scripts {
+                let path = path.to_string_lossy().to_string();
+                let content = std::fs::read_to_string(&path)?;
+                let tree = parser::cst::parse_tree(&content)
+                    .map_err(|error| report_syntax_error(&path, &content, &error))?;
+                let formatted = format_script(&tree);
+                if formatted == content {
+                    continue;
+                }
+
+                if check {
+                    println!("{} is not formatted", path);
+                    unformatted += 1;
+                } else {
+                    std::fs::write(&path, formatted)?;
+                }
+            }
+
+            if unformatted > 0 {
+                return Err(anyhow!("{} script(s) are not formatted!", unformatted));
+            }
+        }
+
+        Command::Lsp => lsp::serve()?,
+
         Command::Generate { out, encoding } => {
             let sizes = [("S", 1), ("M", 2), ("L", 3), ("XL", 4)];
 
@@ -317,6 +649,86 @@ pub fn run() -> Result<()> {
     Ok(())
 }
 
+fn parse_script(path: &str, content: &str) -> Result<Vec<Statement>> {
+    parser::parse_source(content).map_err(|error| report_syntax_error(path, content, &error))
+}
+
+/// Prints the syntax error along with the source it points into, returning the error to exit with.
+fn report_syntax_error(path: &str, source: &str, error: &SyntaxError) -> anyhow::Error {
+    eprintln!("{}", render_syntax_error(path, source, error));
+    anyhow!("Couldn't parse `{}`!", path)
+}
+
+fn render_syntax_error(path: &str, source: &str, error: &SyntaxError) -> String {
+    let level = annotate_snippets::Level::Error;
+    render_diagnostic(level, path, source, &error.message, Some(error.span.clone()))
+}
+
+fn read_game(path: &str, decoding: Decoding) -> Result<Game> {
+    let file = File::open(path)?;
+    let reader: Box<dyn Read> = match decoding {
+        Decoding::Zlib => Box::new(ZlibDecoder::new(file)),
+        Decoding::Raw => Box::new(file),
+    };
+    let mut reader = BufReader::new(reader);
+
+    let game = Game::read(&mut reader)?;
+
+    let Ok(0) = reader.read(&mut [0; 1]) else {
+        panic!("not all game data could be read!");
+    };
+    Ok(game)
+}
+
+/// Collects the paths of all scripts in the directory, or the path itself if it is a file.
+fn discover_scripts(path: &Path, scripts: &mut Vec<PathBuf>) -> Result<()> {
+    if !path.is_dir() {
+        scripts.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    let entries = std::fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    for entry in entries.into_iter().sorted() {
+        if entry.is_dir() || entry.extension().is_some_and(|extension| extension == "ds") {
+            discover_scripts(&entry, scripts)?;
+        }
+    }
+    Ok(())
+}
+
+fn render_warning(path: &str, source: &str, warning: &Warning) -> String {
+    render_diagnostic(
+        annotate_snippets::Level::Warning,
+        path,
+        source,
+        &warning.message,
+        warning.span.clone(),
+    )
+}
+
+fn render_diagnostic(
+    level: annotate_snippets::Level,
+    path: &str,
+    source: &str,
+    title: &str,
+    span: Option<Span>,
+) -> String {
+    let mut message = level.title(title);
+    if let Some(span) = span {
+        message = message.snippet(
+            annotate_snippets::Snippet::source(source)
+                .origin(path)
+                .fold(true)
+                .annotation(level.span(span)),
+        );
+    }
+    let renderer = annotate_snippets::Renderer::plain();
+    let rendered = renderer.render(message).to_string();
+    rendered
+}
+
 fn write_game_with_encoding(
     mut writer: &mut impl Write,
     game: Game,
@@ -333,6 +745,9 @@ fn write_game_with_encoding(
         Encoding::Debug => {
             write!(writer, "{:#?}", game)?;
         }
+        Encoding::Dot => {
+            write_dot(writer, &game)?;
+        }
     }
     Ok(())
 }
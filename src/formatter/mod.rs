@@ -0,0 +1,597 @@
+use std::collections::{HashSet, VecDeque};
+
+use itertools::Itertools;
+
+use crate::{
+    lexer::token::Kind,
+    parser::{
+        cst::{Node, SyntaxTree},
+        grammar::{Callback, Expression, Input, Output, Statement, EVENTS},
+    },
+};
+
+/// Lines longer than this get their inputs wrapped onto separate lines.
+const MAX_WIDTH: usize = 100;
+const INDENT: &str = "  ";
+
+/// Reprints a parsed script in canonical style, keeping trailing comments on their line and
+/// blank lines between statements.
+pub fn format_script(tree: &SyntaxTree) -> String {
+    let mut formatter = Formatter {
+        output: String::new(),
+        trivia: Trivia::new(&tree.root),
+    };
+    formatter.statements(&tree.statements, 0);
+    formatter.output
+}
+
+/// Layout of the source that the statements don't record.
+struct Trivia {
+    /// Offsets of the comments in source order and whether they follow other code on their line.
+    comments: VecDeque<(usize, bool)>,
+    /// Offsets of the tokens preceded by an empty line.
+    blank_lines: HashSet<usize>,
+}
+
+impl Trivia {
+    fn new(root: &Node) -> Self {
+        let mut comments = VecDeque::new();
+        let mut blank_lines = HashSet::new();
+        let (mut newlines, mut first) = (0, true);
+        for token in root.tokens() {
+            if token.kind == Kind::Whitespace {
+                newlines += token.value.matches('\n').count();
+                continue;
+            }
+            if newlines > 1 {
+                blank_lines.insert(token.offset);
+            }
+            if token.kind == Kind::Comment {
+                comments.push_back((token.offset, !first && newlines == 0));
+            }
+            (newlines, first) = (0, false);
+        }
+
+        Self {
+            comments,
+            blank_lines,
+        }
+    }
+}
+
+struct Formatter {
+    output: String,
+    trivia: Trivia,
+}
+
+impl Formatter {
+    fn statements(&mut self, statements: &[Statement], depth: usize) {
+        for (i, statement) in statements.iter().enumerate() {
+            let (offset, trailing) = match statement {
+                Statement::Comment(_) => self.trivia.comments.pop_front().unwrap_or_default(),
+                statement => (statement.span().map_or(0, |span| span.start), false),
+            };
+
+            if trailing && self.output.ends_with('\n') {
+                self.output.pop();
+                self.output.push(' ');
+            } else {
+                if i > 0 && self.trivia.blank_lines.contains(&offset) {
+                    self.output.push('\n');
+                }
+                self.output.push_str(&INDENT.repeat(depth));
+            }
+            self.statement(statement, depth);
+            self.output.push('\n');
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement, depth: usize) {
+        if let Some((condition, then, otherwise)) = if_statement(statement) {
+            self.if_statement(condition, then, otherwise, depth);
+            return;
+        }
+        if let Some((counter, start, stop, statements)) = for_statement(statement) {
+            self.output += &format!(
+                "for {} in {}..{}",
+                format_outputs(std::slice::from_ref(counter)),
+                format_expression(start),
+                format_expression(stop)
+            );
+            self.block(statements, depth);
+            return;
+        }
+
+        if let Some((event, inputs, outputs, statements)) = event(statement) {
+            self.output += "on ";
+            match inputs {
+                [] => self.output += event,
+                [Input {
+                    label: None,
+                    value: Expression::String(label),
+                }] => self.output += &format!("{} \"{}\"", event, label),
+                inputs => self.call(event, inputs, depth),
+            }
+            if !outputs.is_empty() {
+                self.output += &format!(" |{}|", format_outputs(outputs));
+            }
+            self.block(statements, depth);
+            return;
+        }
+
+        match statement {
+            Statement::Invocation {
+                name,
+                inputs,
+                outputs,
+                callbacks,
+                ..
+            } => {
+                if !outputs.is_empty() {
+                    self.output += &format!("{} = ", format_outputs(outputs));
+                }
+                self.call(name, inputs, depth);
+                self.callbacks(callbacks, depth);
+            }
+            Statement::Assignement { value, outputs, .. } => {
+                if let Some((symbol, step)) = compound(outputs, value) {
+                    self.output += &format!("{} {}= {}", format_outputs(outputs), symbol, format_expression(step));
+                    return;
+                }
+                if !outputs.is_empty() {
+                    self.output += &format!("{} = ", format_outputs(outputs));
+                }
+                match value {
+                    Expression::Call { name, inputs }
+                        if operator(name, inputs).is_none() && vector_keyword(name, inputs).is_none() =>
+                    {
+                        self.call(name, inputs, depth)
+                    }
+                    value => self.output += &format_expression(value),
+                }
+            }
+            Statement::While {
+                condition,
+                statements,
+                ..
+            } => {
+                self.output += &format!("while {}", format_expression(condition));
+                self.block(statements, depth);
+            }
+            Statement::Definition {
+                name,
+                inputs,
+                outputs,
+                callbacks,
+                statements,
+                ..
+            } => {
+                self.output += &format!("def {}({})", name, inputs.join(", "));
+                if !callbacks.is_empty() {
+                    self.output += &format!(" {}", callbacks.join(", "));
+                }
+                if !outputs.is_empty() {
+                    self.output += &format!(" |{}|", outputs.join(", "));
+                }
+                self.block(statements, depth);
+            }
+            Statement::Comment(text) if text.is_empty() => self.output.push('#'),
+            Statement::Comment(text) => self.output += &format!("# {}", text),
+        }
+    }
+
+    /// Prints an `if` statement, chaining an `if` that is alone in the `false` callback with
+    /// `else if`.
+    fn if_statement(&mut self, condition: &Expression, then: &[Statement], otherwise: Option<&[Statement]>, depth: usize) {
+        self.output += &format!("if {}", format_expression(condition));
+        self.block(then, depth);
+        let Some(otherwise) = otherwise else {
+            return;
+        };
+        let chained = match otherwise {
+            [statement] => if_statement(statement),
+            _ => None,
+        };
+        match chained {
+            Some((condition, then, otherwise)) => {
+                self.output += " else ";
+                self.if_statement(condition, then, otherwise, depth);
+            }
+            None => {
+                self.output += " else";
+                self.block(otherwise, depth);
+            }
+        }
+    }
+
+    /// Prints a call, wrapping its inputs if it doesn't fit on the line.
+    fn call(&mut self, name: &str, inputs: &[Input], depth: usize) {
+        let inputs = inputs.iter().map(format_input).collect_vec();
+        let line = format!("{}({})", name, inputs.join(", "));
+        let column = self.output.len() - self.output.rfind('\n').map_or(0, |i| i + 1);
+
+        if column + line.len() <= MAX_WIDTH || inputs.is_empty() {
+            self.output += &line;
+            return;
+        }
+        let indent = INDENT.repeat(depth + 1);
+        self.output += &format!("{}(\n", name);
+        self.output += &inputs
+            .iter()
+            .map(|input| format!("{}{}", indent, input))
+            .join(",\n");
+        self.output += &format!("\n{})", INDENT.repeat(depth));
+    }
+
+    fn callbacks(&mut self, callbacks: &[Callback], depth: usize) {
+        for callback in callbacks {
+            if let Some(label) = &callback.label {
+                self.output += &format!(" {}", label);
+            }
+            if !callback.outputs.is_empty() {
+                self.output += &format!(" |{}|", format_outputs(&callback.outputs));
+            }
+            self.block(&callback.statements, depth);
+        }
+    }
+
+    fn block(&mut self, statements: &[Statement], depth: usize) {
+        if statements.is_empty() {
+            self.output += " {}";
+            return;
+        }
+        self.output += " {\n";
+        self.statements(statements, depth + 1);
+        self.output += &format!("{}}}", INDENT.repeat(depth));
+    }
+}
+
+/// The condition, statements and `else` statements of an `if` statement.
+type Branches<'a> = (&'a Expression, &'a [Statement], Option<&'a [Statement]>);
+
+/// The branches an `if` node can be written as.
+fn if_statement(statement: &Statement) -> Option<Branches<'_>> {
+    let Statement::Invocation {
+        name,
+        inputs,
+        outputs,
+        callbacks,
+        ..
+    } = statement
+    else {
+        return None;
+    };
+    let [condition] = &inputs[..] else {
+        return None;
+    };
+    if name != "if" || condition.label.is_some() || !outputs.is_empty() {
+        return None;
+    }
+    match &callbacks[..] {
+        [then] if then.label.as_deref() == Some("true") && then.outputs.is_empty() => {
+            Some((&condition.value, &then.statements, None))
+        }
+        [then, otherwise]
+            if then.label.as_deref() == Some("true")
+                && otherwise.label.as_deref() == Some("false")
+                && then.outputs.is_empty()
+                && otherwise.outputs.is_empty() =>
+        {
+            Some((&condition.value, &then.statements, Some(&otherwise.statements)))
+        }
+        _ => None,
+    }
+}
+
+/// The counter, start, stop and body of a `loop` node that can be written as a `for` statement.
+/// Loops with a skipped bound keep the callback form, as a range can't leave one out.
+fn for_statement(statement: &Statement) -> Option<(&Output, &Expression, &Expression, &[Statement])> {
+    const SKIPPED: &Output = &Output {
+        label: None,
+        modifier: None,
+        name: None,
+        index: None,
+        member: None,
+    };
+
+    let Statement::Invocation {
+        name,
+        inputs,
+        outputs,
+        callbacks,
+        ..
+    } = statement
+    else {
+        return None;
+    };
+    let ([start, stop], [body]) = (&inputs[..], &callbacks[..]) else {
+        return None;
+    };
+    if name != "loop" || start.label.is_some() || stop.label.is_some() || !outputs.is_empty() {
+        return None;
+    }
+    if matches!(start.value, Expression::Skip) || matches!(stop.value, Expression::Skip) {
+        return None;
+    }
+    if body.label.as_deref().is_some_and(|label| label != "do") {
+        return None;
+    }
+    let counter = match &body.outputs[..] {
+        [] => SKIPPED,
+        [counter] if counter.label.is_none() => counter,
+        _ => return None,
+    };
+    Some((counter, &start.value, &stop.value, &body.statements))
+}
+
+/// The event, inputs, outputs and body of an `on` handler.
+type Handler<'a> = (&'static str, &'a [Input], &'a [Output], &'a [Statement]);
+
+/// The handler a sensor node can be written as.
+fn event(statement: &Statement) -> Option<Handler<'_>> {
+    let Statement::Invocation {
+        name,
+        inputs,
+        outputs,
+        callbacks,
+        ..
+    } = statement
+    else {
+        return None;
+    };
+    let (event, _) = EVENTS.iter().find(|(_, sensor)| sensor == name)?;
+    let [body] = &callbacks[..] else {
+        return None;
+    };
+    if !outputs.is_empty() || body.label.is_some() {
+        return None;
+    }
+    Some((event, inputs, &body.outputs, &body.statements))
+}
+
+fn format_outputs(outputs: &[Output]) -> String {
+    outputs
+        .iter()
+        .map(|output| {
+            let mut name = match (&output.modifier, &output.name) {
+                (_, None) => "_".to_string(),
+                (Some(modifier), Some(name)) => format!("{}{}", modifier.prefix(), name),
+                (None, Some(name)) => name.clone(),
+            };
+            if let Some(index) = &output.index {
+                name += &format!("[{}]", format_expression(index));
+            }
+            if let Some(member) = &output.member {
+                name += &format!(".{}", member);
+            }
+            match &output.label {
+                Some(label) => format!("{}: {}", label, name),
+                None => name,
+            }
+        })
+        .join(", ")
+}
+
+fn format_input(input: &Input) -> String {
+    let value = format_expression(&input.value);
+    match &input.label {
+        Some(label) => format!("{}: {}", label, value),
+        None => value,
+    }
+}
+
+/// Binding strength of expressions, from the loosest operators to literals.
+const ATOM: u8 = 8;
+const PREFIX: u8 = 7;
+const POWER: u8 = 6;
+
+/// Finds the operator a call was parsed from along with its precedence.
+fn operator(name: &str, inputs: &[Input]) -> Option<(&'static str, u8)> {
+    if inputs.iter().any(|input| input.label.is_some()) {
+        return None;
+    }
+    let (symbol, precedence, arity) = match name {
+        "or" => ("or", 1, 2),
+        "and" => ("and", 1, 2),
+        "equal" => ("==", 2, 2),
+        "not_equal" => ("!=", 2, 2),
+        "less_than" => ("<", 3, 2),
+        "at_most" => ("<=", 3, 2),
+        "greater_than" => (">", 3, 2),
+        "at_least" => (">=", 3, 2),
+        "add" => ("+", 4, 2),
+        "subtract" => ("-", 4, 2),
+        "multiply" => ("*", 5, 2),
+        "divide" => ("/", 5, 2),
+        "power" => ("**", POWER, 2),
+        "not" => ("not ", PREFIX, 1),
+        "negate" => ("-", PREFIX, 1),
+        _ => return None,
+    };
+    (inputs.len() == arity).then_some((symbol, precedence))
+}
+
+/// Finds the compound assignement an assignement was parsed from, like `x += e` for `x = x + e`,
+/// along with the value it's combined with.
+fn compound<'a>(outputs: &[Output], value: &'a Expression) -> Option<(&'static str, &'a Expression)> {
+    let ([output], Expression::Call { name, inputs }) = (outputs, value) else {
+        return None;
+    };
+    let (symbol, _) = operator(name, inputs)?;
+    let [target, step] = &inputs[..] else {
+        return None;
+    };
+    if !matches!(symbol, "+" | "-" | "*" | "/") || output.label.is_some() {
+        return None;
+    }
+    (output.to_expression().as_ref() == Some(&target.value)).then_some((symbol, &step.value))
+}
+
+/// Finds the literal a vector or rotation was parsed from.
+fn vector_keyword(name: &str, inputs: &[Input]) -> Option<&'static str> {
+    if inputs.len() != 3 || inputs.iter().any(|input| input.label.is_some()) {
+        return None;
+    }
+    match name {
+        "make_vector" => Some("vec"),
+        "make_rotation" => Some("rot"),
+        _ => None,
+    }
+}
+
+fn precedence(expression: &Expression) -> u8 {
+    match expression {
+        Expression::Call { name, inputs } => {
+            operator(name, inputs).map_or(ATOM, |(_, precedence)| precedence)
+        }
+        _ => ATOM,
+    }
+}
+
+/// Prints an expression with operators in place of their calls and only the parentheses needed
+/// to parse it back the same way.
+pub fn format_expression(expression: &Expression) -> String {
+    match expression {
+        Expression::Skip => "_".to_string(),
+        Expression::Float(value) => {
+            let value = value.to_string();
+            if value.contains('.') {
+                value
+            } else {
+                format!("{}.0", value)
+            }
+        }
+        Expression::Integer(value) => value.to_string(),
+        Expression::Boolean(true) => "True".to_string(),
+        Expression::Boolean(false) => "False".to_string(),
+        Expression::String(value) => format!("\"{}\"", value),
+        Expression::Vector([x, y, z]) => format!("vec({}, {}, {})", x, y, z),
+        Expression::Rotation([x, y, z]) => format!("rot({}, {}, {})", x, y, z),
+        Expression::Variable { modifier, name } => match modifier {
+            Some(modifier) => format!("{}{}", modifier.prefix(), name),
+            None => name.clone(),
+        },
+        Expression::Reference(variable) => format!("&{}", format_expression(variable)),
+        Expression::List(items) => format!("[{}]", items.iter().map(format_expression).join(", ")),
+        Expression::Index { list, index } => format!("{}[{}]", format_postfixed(list), format_expression(index)),
+        Expression::Member { value, member } => format!("{}.{}", format_postfixed(value), member),
+        Expression::Method { value, name, inputs } => format!(
+            "{}.{}({})",
+            format_postfixed(value),
+            name,
+            inputs.iter().map(format_input).join(", ")
+        ),
+        Expression::Call { name, inputs } => {
+            if let Some(keyword) = vector_keyword(name, inputs) {
+                return format!("{}({})", keyword, inputs.iter().map(format_input).join(", "));
+            }
+            let Some((symbol, precedence)) = operator(name, inputs) else {
+                return format!("{}({})", name, inputs.iter().map(format_input).join(", "));
+            };
+            let operand = |input: &Input, minimum: u8| {
+                let value = format_expression(&input.value);
+                if self::precedence(&input.value) < minimum {
+                    format!("({})", value)
+                } else {
+                    value
+                }
+            };
+
+            match &inputs[..] {
+                // Prefix operators only apply to simple expressions.
+                [a] => format!("{}{}", symbol, operand(a, ATOM)),
+                // Powers associate to the right, everything else to the left.
+                [a, b] if precedence == POWER => {
+                    format!("{} {} {}", operand(a, POWER + 1), symbol, operand(b, POWER))
+                }
+                [a, b] => format!(
+                    "{} {} {}",
+                    operand(a, precedence),
+                    symbol,
+                    operand(b, precedence + 1)
+                ),
+                _ => unreachable!(),
+            }
+        }
+    }
+}
+
+/// Prints an expression followed by an index or member, which bind tighter than any operator.
+fn format_postfixed(expression: &Expression) -> String {
+    match precedence(expression) {
+        ATOM => format_expression(expression),
+        _ => format!("({})", format_expression(expression)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_script;
+    use crate::parser::cst::parse_tree;
+
+    fn format(source: &str) -> String {
+        format_script(&parse_tree(source).unwrap())
+    }
+
+    #[test]
+    fn keeps_formatted_scripts() {
+        let source = "speed = 2 * 3
+player = shrub()
+inspect(speed + 1)
+if speed > 5 {
+  win()
+} else if speed > 2 {
+  inspect(speed)
+} else {
+  lose()
+}
+for i in 0..5 {
+  inspect(i)
+}
+while speed < 100 {
+  speed *= 2
+}
+def lerp(from, to, amount) |result| {
+  result = from + (to - from) * amount
+}
+";
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn keeps_loops_with_skipped_bounds() {
+        let source = "loop(_, 5) do |i| {
+  inspect(i)
+}
+loop(2, _) do |i| {
+  inspect(i)
+}
+";
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn keeps_method_calls() {
+        let source = "player = shrub()
+player.add_force(vec(0, 10, 0))
+speed = player.get_velocity().x
+";
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn keeps_comments_and_blank_lines() {
+        let source = "# setup\nscore=0 # reset\n\n\nif(score>10) true{\n  # done\n  win()\n}\n";
+        let expected = "# setup\nscore = 0 # reset\n\nif score > 10 {\n  # done\n  win()\n}\n";
+        assert_eq!(format(source), expected);
+        assert_eq!(format(expected), expected);
+    }
+
+    #[test]
+    fn reprints_in_canonical_style() {
+        let source = "score=0\nif(score>10) true{win()} false{ lose( ) }\nx = (1 + 2) * 3\n";
+        let expected = "score = 0\nif score > 10 {\n  win()\n} else {\n  lose()\n}\nx = (1 + 2) * 3\n";
+        assert_eq!(format(source), expected);
+        assert_eq!(format(expected), expected);
+    }
+}
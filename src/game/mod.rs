@@ -262,21 +262,21 @@ pub struct Port {
     pub offset: [u16; 3],
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[allow(unused)]
 pub enum WireKind {
     Execute,
     Value(ValueKind),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[allow(unused)]
 pub enum ValueKind {
     Raw(RawKind),
     Reference(RawKind),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[allow(unused)]
 pub enum RawKind {
     Number,
@@ -0,0 +1,196 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    ops::RangeInclusive,
+};
+
+use crate::{
+    interpreter::line_number,
+    parser::grammar::Statement,
+    transpiler::{
+        graph::{Endpoint, Graph, NodeId},
+        prefabs::Prefab,
+    },
+};
+
+/// How often nodes and their callbacks ran in an interpreter.
+#[derive(Debug, Default)]
+pub struct Hits {
+    pub nodes: HashMap<NodeId, u64>,
+    /// Keyed by the execute port of the callback.
+    pub callbacks: HashMap<Endpoint, u64>,
+}
+
+/// Coverage of a script mapped back to the lines of its source, which can collect the hits of
+/// several runs.
+#[derive(Debug)]
+pub struct Report<'a> {
+    source: &'a str,
+    lines: BTreeMap<usize, u64>,
+    /// Keyed by line, prefab, port and callback name.
+    callbacks: BTreeMap<(usize, String, usize, String), u64>,
+    definitions: Vec<(String, RangeInclusive<usize>)>,
+}
+
+impl<'a> Report<'a> {
+    pub fn new(source: &'a str, statements: &[Statement]) -> Self {
+        let definitions = statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::Definition { name, span, .. } => Some((
+                    name.clone(),
+                    line_number(source, span.start)..=line_number(source, span.end.saturating_sub(1)),
+                )),
+                _ => None,
+            })
+            .collect();
+
+        Self {
+            source,
+            lines: BTreeMap::new(),
+            callbacks: BTreeMap::new(),
+            definitions,
+        }
+    }
+
+    /// Adds the hits of a run of the graph, which must be built from the same source.
+    pub fn record(&mut self, graph: &Graph, prefabs: &HashMap<String, Prefab>, hits: &Hits) {
+        for (node, data) in graph.nodes.iter().enumerate() {
+            let Some(span) = &data.span else {
+                continue;
+            };
+            let line = line_number(self.source, span.start);
+            *self.lines.entry(line).or_default() += hits.nodes.get(&node).copied().unwrap_or_default();
+
+            let Some(prefab) = prefabs.get(&data.prefab) else {
+                continue;
+            };
+            for (i, callback) in prefab.callbacks.iter().enumerate() {
+                let count = hits
+                    .callbacks
+                    .get(&Endpoint { node, port: i + 1 })
+                    .copied()
+                    .unwrap_or_default();
+                *self
+                    .callbacks
+                    .entry((line, prefab.name.clone(), i, callback.name.clone()))
+                    .or_default() += count;
+            }
+        }
+    }
+
+    /// Renders the source annotated with how often each line ran, followed by a summary.
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+
+        for (i, text) in self.source.lines().enumerate() {
+            let line = i + 1;
+            let count = match self.lines.get(&line) {
+                None => "-".to_string(),
+                Some(0) => "#####".to_string(),
+                Some(count) => count.to_string(),
+            };
+            let _ = writeln!(output, "{:>8} | {:>4} | {}", count, line, text);
+
+            let callbacks = self.callbacks.iter().filter(|((other, ..), _)| *other == line);
+            for ((_, prefab, _, callback), count) in callbacks {
+                let _ = match count {
+                    0 => writeln!(output, "{:>8} | {:>4} |   `{}` of `{}` never ran", "", "", callback, prefab),
+                    count => writeln!(
+                        output,
+                        "{:>8} | {:>4} |   `{}` of `{}` ran {} time(s)",
+                        "", "", callback, prefab, count
+                    ),
+                };
+            }
+        }
+
+        let covered = |counts: &mut dyn Iterator<Item = u64>| {
+            let (hit, total) = counts.fold((0, 0), |(hit, total), count| (hit + (count > 0) as usize, total + 1));
+            format!("{}/{}", hit, total)
+        };
+        let definitions = self.definitions.iter().map(|(_, lines)| {
+            self.lines
+                .range(lines.clone())
+                .map(|(_, count)| *count)
+                .sum::<u64>()
+        });
+
+        let _ = writeln!(output, "lines: {}", covered(&mut self.lines.values().copied()));
+        let _ = writeln!(output, "callbacks: {}", covered(&mut self.callbacks.values().copied()));
+        let _ = writeln!(output, "definitions: {}", covered(&mut definitions.clone()));
+        for ((name, _), count) in self.definitions.iter().zip(definitions) {
+            if count == 0 {
+                let _ = writeln!(output, "definition `{}` never ran", name);
+            }
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Report;
+    use crate::{
+        interpreter::Interpreter,
+        parser::parse_source,
+        transpiler::{build_graph, prefabs::get_prefabs},
+    };
+
+    const SOURCE: &str = "$x += 1
+if $x > 5 {
+  $y = 1
+} else {
+  $y = 2
+}
+def unused() {
+  $z = 1
+}
+";
+
+    /// Runs the script for the number of frames, recording its hits into the report.
+    fn record(report: &mut Report, source: &str, frames: u64) {
+        let prefabs = get_prefabs();
+        let graph = build_graph(parse_source(source).unwrap(), &prefabs, &mut Vec::new()).unwrap();
+        let mut interpreter = Interpreter::new(&graph, &prefabs);
+        for _ in 0..frames {
+            interpreter.run_frame().unwrap();
+        }
+        report.record(&graph, &prefabs, interpreter.hits());
+    }
+
+    #[test]
+    fn renders_hits_per_line_and_callback() {
+        let mut report = Report::new(SOURCE, &parse_source(SOURCE).unwrap());
+        record(&mut report, SOURCE, 3);
+        let expected = "       3 |    1 | $x += 1
+       3 |    2 | if $x > 5 {
+         |      |   `true` of `if` never ran
+         |      |   `false` of `if` ran 3 time(s)
+   ##### |    3 |   $y = 1
+       - |    4 | } else {
+       3 |    5 |   $y = 2
+       - |    6 | }
+       - |    7 | def unused() {
+       - |    8 |   $z = 1
+       - |    9 | }
+lines: 3/4
+callbacks: 1/2
+definitions: 0/1
+definition `unused` never ran
+";
+        assert_eq!(report.render(), expected);
+    }
+
+    #[test]
+    fn adds_up_several_runs() {
+        let mut report = Report::new(SOURCE, &parse_source(SOURCE).unwrap());
+        record(&mut report, SOURCE, 3);
+        record(&mut report, SOURCE, 6);
+        let output = report.render();
+        assert!(output.contains("       9 |    1 | $x += 1\n"));
+        assert!(output.contains("`true` of `if` ran 1 time(s)"));
+        assert!(output.contains("`false` of `if` ran 8 time(s)"));
+        assert!(output.contains("lines: 4/4\ncallbacks: 2/2\n"));
+    }
+}
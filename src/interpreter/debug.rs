@@ -0,0 +1,236 @@
+use anyhow::Result;
+use itertools::Itertools;
+use std::{
+    collections::BTreeSet,
+    error::Error,
+    fmt::Display,
+    io::{BufRead, Write},
+};
+
+use crate::{
+    interpreter::{line_number, Hook, Interpreter, Value},
+    transpiler::graph::NodeId,
+};
+
+const HELP: &str = "\
+commands:
+  step, s             execute the next node
+  continue, c         run until the next breakpoint
+  break, b <line>     stop before nodes on the line
+  delete, d <line>    remove the breakpoint on the line
+  breakpoints         list all breakpoints
+  inputs, i           show the values on the input wires of the next node
+  print, p <name>     show the values of a variable
+  variables, v        show all variables
+  frame, f            show the current frame
+  list, l             show the source around the next node
+  quit, q             stop debugging";
+
+/// Raised when the user stops debugging.
+#[derive(Debug)]
+pub struct Stopped;
+
+impl Display for Stopped {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Debugging stopped")
+    }
+}
+
+impl Error for Stopped {}
+
+/// Pauses the interpreter on breakpoints and while stepping, taking commands from the input,
+/// usually the terminal.
+pub struct Debugger<'a> {
+    source: &'a str,
+    breakpoints: BTreeSet<usize>,
+    stepping: bool,
+    input: Box<dyn BufRead + 'a>,
+    output: Box<dyn Write + 'a>,
+}
+
+impl<'a> Debugger<'a> {
+    /// Creates a debugger that starts out stepping if there are no breakpoints.
+    pub fn new(
+        source: &'a str,
+        breakpoints: impl IntoIterator<Item = usize>,
+        input: impl BufRead + 'a,
+        output: impl Write + 'a,
+    ) -> Self {
+        let breakpoints = breakpoints.into_iter().collect::<BTreeSet<_>>();
+        Self {
+            source,
+            stepping: breakpoints.is_empty(),
+            breakpoints,
+            input: Box::new(input),
+            output: Box::new(output),
+        }
+    }
+
+    fn line(&self, interpreter: &Interpreter, node: NodeId) -> Option<usize> {
+        let span = interpreter.graph.nodes[node].span.as_ref()?;
+        Some(line_number(self.source, span.start))
+    }
+
+    fn list(&mut self, line: Option<usize>) -> Result<()> {
+        let Some(line) = line else {
+            writeln!(self.output, "no source for this node")?;
+            return Ok(());
+        };
+        let lines = self.source.lines().collect_vec();
+        let start = line.saturating_sub(3);
+        let end = (line + 2).min(lines.len());
+        for (number, text) in lines.iter().enumerate().take(end).skip(start) {
+            let marker = if number + 1 == line { ">" } else { " " };
+            writeln!(self.output, "{} {:>4} | {}", marker, number + 1, text)?;
+        }
+        Ok(())
+    }
+
+    fn prompt(&mut self, interpreter: &mut Interpreter, node: NodeId, line: Option<usize>) -> Result<()> {
+        loop {
+            write!(self.output, "(debug) ")?;
+            self.output.flush()?;
+
+            let mut command = String::new();
+            if self.input.read_line(&mut command)? == 0 {
+                return Err(Stopped.into());
+            }
+            let mut words = command.split_whitespace();
+            let (command, argument) = (words.next().unwrap_or("step"), words.next());
+
+            match (command, argument.map(str::parse::<usize>)) {
+                ("step" | "s", None) => {
+                    self.stepping = true;
+                    return Ok(());
+                }
+                ("continue" | "c", None) => {
+                    self.stepping = false;
+                    return Ok(());
+                }
+                ("break" | "b", Some(Ok(line))) => {
+                    self.breakpoints.insert(line);
+                    writeln!(self.output, "breakpoint on line {}", line)?;
+                }
+                ("delete" | "d", Some(Ok(line))) => {
+                    if !self.breakpoints.remove(&line) {
+                        writeln!(self.output, "no breakpoint on line {}", line)?;
+                    }
+                }
+                ("breakpoints", None) => {
+                    writeln!(self.output, "{}", self.breakpoints.iter().join(", "))?;
+                }
+                ("inputs" | "i", None) => {
+                    let prefab = interpreter.prefab(node)?;
+                    for (port, input) in prefab.inputs.iter().enumerate() {
+                        let value = interpreter.input(node, port)?;
+                        let suffix = if interpreter.is_connected(node, port) { "" } else { " (default)" };
+                        writeln!(self.output, "{} = {}{}", input.name, value, suffix)?;
+                    }
+                }
+                ("print" | "p", Some(_)) => {
+                    let name = argument.unwrap_or_default();
+                    match interpreter.variables.get(name) {
+                        Some(values) => writeln!(self.output, "{} = {}", name, format_values(values))?,
+                        None => writeln!(self.output, "{} is not set", name)?,
+                    }
+                }
+                ("variables" | "v", None) => {
+                    for (name, values) in interpreter.variables.iter().sorted_by_key(|(name, _)| *name) {
+                        writeln!(self.output, "{} = {}", name, format_values(values))?;
+                    }
+                }
+                ("frame" | "f", None) => writeln!(self.output, "frame {}", interpreter.frame)?,
+                ("list" | "l", None) => self.list(line)?,
+                ("quit" | "q", None) => return Err(Stopped.into()),
+                _ => writeln!(self.output, "{}", HELP)?,
+            }
+        }
+    }
+}
+
+impl Hook for Debugger<'_> {
+    fn before_step(&mut self, interpreter: &mut Interpreter, node: NodeId) -> Result<()> {
+        let line = self.line(interpreter, node);
+        let on_breakpoint = line.is_some_and(|line| self.breakpoints.contains(&line));
+        if !self.stepping && !on_breakpoint {
+            return Ok(());
+        }
+
+        let prefab = &interpreter.graph.nodes[node].prefab;
+        match line {
+            Some(line) => writeln!(self.output, "frame {}, line {}: {}#{}", interpreter.frame, line, prefab, node)?,
+            None => writeln!(self.output, "frame {}: {}#{}", interpreter.frame, prefab, node)?,
+        }
+        self.prompt(interpreter, node, line)
+    }
+}
+
+fn format_values(values: &[Value]) -> String {
+    match values {
+        [value] => value.to_string(),
+        values => format!("[{}]", values.iter().join(", ")),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Debugger, Stopped};
+    use crate::{
+        interpreter::Interpreter,
+        parser::parse_source,
+        transpiler::{build_graph, prefabs::get_prefabs},
+    };
+
+    /// Runs a frame of the script under the debugger fed with the commands, returning what it
+    /// printed and whether the user stopped it.
+    fn debug(source: &str, breakpoints: &[usize], commands: &str) -> (String, bool) {
+        let prefabs = get_prefabs();
+        let graph = build_graph(parse_source(source).unwrap(), &prefabs, &mut Vec::new()).unwrap();
+        let mut output = Vec::new();
+        let stopped = {
+            let mut interpreter = Interpreter::new(&graph, &prefabs);
+            let debugger = Debugger::new(source, breakpoints.iter().copied(), commands.as_bytes(), &mut output);
+            interpreter.set_hook(Box::new(debugger));
+            match interpreter.run_frame() {
+                Ok(_) => false,
+                Err(error) if error.is::<Stopped>() => true,
+                Err(error) => panic!("{}", error),
+            }
+        };
+        (String::from_utf8(output).unwrap(), stopped)
+    }
+
+    #[test]
+    fn stops_on_breakpoints() {
+        let (output, stopped) = debug("$x = 1\n$x = 2\n$x = 3", &[2], "print $x\ncontinue\n");
+        assert!(!stopped);
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("frame 0, line 2: set_number#"));
+        assert_eq!(lines[1..], ["(debug) $x = 1", "(debug) "]);
+    }
+
+    #[test]
+    fn steps_through_every_node() {
+        let (output, stopped) = debug("$x = 1\n$x = 2", &[], "step\nstep\n");
+        assert!(!stopped);
+        assert_eq!(output.matches("(debug) ").count(), 2);
+        assert!(output.contains("line 1: set_number#"));
+        assert!(output.contains("line 2: set_number#"));
+    }
+
+    #[test]
+    fn stops_on_quit_and_end_of_input() {
+        assert!(debug("$x = 1\n$x = 2", &[], "quit\n").1);
+        assert!(debug("$x = 1\n$x = 2", &[], "step\n").1);
+    }
+
+    #[test]
+    fn manages_breakpoints() {
+        let (output, _) = debug("$x = 1", &[], "break 3\nbreak 1\ndelete 3\ndelete 5\nbreakpoints\nquit\n");
+        assert!(output.contains("breakpoint on line 3"));
+        assert!(output.contains("no breakpoint on line 5"));
+        assert!(output.contains("(debug) 1\n"));
+    }
+}
@@ -0,0 +1,128 @@
+/// Quaternion as `[x, y, z, w]`.
+pub type Quaternion = [f64; 4];
+
+/// Converts euler angles in degrees to a quaternion, rotating around z, then x, then y.
+pub fn from_euler(euler: [f64; 3]) -> Quaternion {
+    let [x, y, z] = euler.map(|angle| angle.to_radians() / 2.0);
+    let qx = [x.sin(), 0.0, 0.0, x.cos()];
+    let qy = [0.0, y.sin(), 0.0, y.cos()];
+    let qz = [0.0, 0.0, z.sin(), z.cos()];
+    multiply(multiply(qy, qx), qz)
+}
+
+/// Converts a quaternion to euler angles in degrees, the inverse of [`from_euler`].
+pub fn to_euler(q: Quaternion) -> [f64; 3] {
+    let [x, y, z, w] = q;
+    let sin_x = (2.0 * (w * x - y * z)).clamp(-1.0, 1.0);
+    let angles = [
+        sin_x.asin(),
+        (2.0 * (w * y + x * z)).atan2(1.0 - 2.0 * (x * x + y * y)),
+        (2.0 * (w * z + x * y)).atan2(1.0 - 2.0 * (x * x + z * z)),
+    ];
+    angles.map(f64::to_degrees)
+}
+
+pub fn multiply(a: Quaternion, b: Quaternion) -> Quaternion {
+    [
+        a[3] * b[0] + a[0] * b[3] + a[1] * b[2] - a[2] * b[1],
+        a[3] * b[1] - a[0] * b[2] + a[1] * b[3] + a[2] * b[0],
+        a[3] * b[2] + a[0] * b[1] - a[1] * b[0] + a[2] * b[3],
+        a[3] * b[3] - a[0] * b[0] - a[1] * b[1] - a[2] * b[2],
+    ]
+}
+
+pub fn inverse(q: Quaternion) -> Quaternion {
+    [-q[0], -q[1], -q[2], q[3]]
+}
+
+pub fn rotate(q: Quaternion, v: [f64; 3]) -> [f64; 3] {
+    let axis = [q[0], q[1], q[2]];
+    let t = cross(axis, v).map(|c| c * 2.0);
+    let u = cross(axis, t);
+    [
+        v[0] + q[3] * t[0] + u[0],
+        v[1] + q[3] * t[1] + u[1],
+        v[2] + q[3] * t[2] + u[2],
+    ]
+}
+
+pub fn slerp(a: Quaternion, b: Quaternion, amount: f64) -> Quaternion {
+    let mut dot = a[0] * b[0] + a[1] * b[1] + a[2] * b[2] + a[3] * b[3];
+    // Take the shorter way around.
+    let b = if dot < 0.0 {
+        dot = -dot;
+        b.map(|c| -c)
+    } else {
+        b
+    };
+
+    let (wa, wb) = if dot > 0.9995 {
+        (1.0 - amount, amount)
+    } else {
+        let theta = dot.acos();
+        (
+            ((1.0 - amount) * theta).sin() / theta.sin(),
+            (amount * theta).sin() / theta.sin(),
+        )
+    };
+    normalize_quaternion([
+        wa * a[0] + wb * b[0],
+        wa * a[1] + wb * b[1],
+        wa * a[2] + wb * b[2],
+        wa * a[3] + wb * b[3],
+    ])
+}
+
+pub fn axis_angle(axis: [f64; 3], angle: f64) -> Quaternion {
+    let axis = normalize(axis);
+    let half = angle.to_radians() / 2.0;
+    [axis[0] * half.sin(), axis[1] * half.sin(), axis[2] * half.sin(), half.cos()]
+}
+
+/// Rotation that points the z axis along the direction, keeping the y axis close to up.
+pub fn look_rotation(direction: [f64; 3], up: [f64; 3]) -> Quaternion {
+    let forward = normalize(direction);
+    let right = normalize(cross(up, forward));
+    let up = cross(forward, right);
+
+    let (m00, m01, m02) = (right[0], up[0], forward[0]);
+    let (m10, m11, m12) = (right[1], up[1], forward[1]);
+    let (m20, m21, m22) = (right[2], up[2], forward[2]);
+
+    let trace = m00 + m11 + m22;
+    let q = if trace > 0.0 {
+        let s = (trace + 1.0).sqrt() * 2.0;
+        [(m21 - m12) / s, (m02 - m20) / s, (m10 - m01) / s, s / 4.0]
+    } else if m00 > m11 && m00 > m22 {
+        let s = (1.0 + m00 - m11 - m22).sqrt() * 2.0;
+        [s / 4.0, (m01 + m10) / s, (m02 + m20) / s, (m21 - m12) / s]
+    } else if m11 > m22 {
+        let s = (1.0 + m11 - m00 - m22).sqrt() * 2.0;
+        [(m01 + m10) / s, s / 4.0, (m12 + m21) / s, (m02 - m20) / s]
+    } else {
+        let s = (1.0 + m22 - m00 - m11).sqrt() * 2.0;
+        [(m02 + m20) / s, (m12 + m21) / s, s / 4.0, (m10 - m01) / s]
+    };
+    normalize_quaternion(q)
+}
+
+pub fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+pub fn normalize(v: [f64; 3]) -> [f64; 3] {
+    let length = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    if length == 0.0 {
+        return [0.0; 3];
+    }
+    v.map(|c| c / length)
+}
+
+fn normalize_quaternion(q: Quaternion) -> Quaternion {
+    let length = (q[0] * q[0] + q[1] * q[1] + q[2] * q[2] + q[3] * q[3]).sqrt();
+    q.map(|c| c / length)
+}
@@ -0,0 +1,691 @@
+use anyhow::{anyhow, Result};
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{
+    game::{OptData, RawKind, ValueKind},
+    transpiler::{
+        fold::{self, Constant},
+        graph::{Endpoint, Graph, NodeId},
+        prefabs::Prefab,
+    },
+};
+use coverage::Hits;
+use random::Random;
+use testing::{AssertionFailed, Mocks};
+
+pub mod coverage;
+pub mod debug;
+mod math;
+mod random;
+pub mod testing;
+
+/// A value flowing through a wire at runtime.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Vector([f64; 3]),
+    Rotation([f64; 3]),
+    Truth(bool),
+    /// The node of the object, if there is one.
+    Object(Option<NodeId>),
+    Constraint(Option<NodeId>),
+    Reference(Pointer),
+}
+
+impl Value {
+    fn default(kind: RawKind) -> Self {
+        match kind {
+            RawKind::Number => Value::Number(0.0),
+            RawKind::Vector => Value::Vector([0.0; 3]),
+            RawKind::Rotation => Value::Rotation([0.0; 3]),
+            RawKind::Truth => Value::Truth(false),
+            RawKind::Object => Value::Object(None),
+            RawKind::Constraint => Value::Constraint(None),
+        }
+    }
+
+    fn constant(&self) -> Option<Constant> {
+        match *self {
+            Value::Number(value) => Some(Constant::Number(value)),
+            Value::Vector(value) => Some(Constant::Vector(value)),
+            Value::Rotation(value) => Some(Constant::Rotation(value)),
+            Value::Truth(value) => Some(Constant::Truth(value)),
+            _ => None,
+        }
+    }
+
+    fn from_constant(constant: Constant) -> Self {
+        match constant {
+            Constant::Number(value) => Value::Number(value),
+            Constant::Vector(value) => Value::Vector(value),
+            Constant::Rotation(value) => Value::Rotation(value),
+            Constant::Truth(value) => Value::Truth(value),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Number(value) => write!(f, "{}", format_number(*value)),
+            Value::Vector([x, y, z]) | Value::Rotation([x, y, z]) => write!(
+                f,
+                "({}, {}, {})",
+                format_number(*x),
+                format_number(*y),
+                format_number(*z)
+            ),
+            Value::Truth(true) => write!(f, "True"),
+            Value::Truth(false) => write!(f, "False"),
+            Value::Object(Some(node)) | Value::Constraint(Some(node)) => write!(f, "#{}", node),
+            Value::Object(None) | Value::Constraint(None) => write!(f, "None"),
+            Value::Reference(pointer) => write!(f, "&{}[{}]", pointer.variable, pointer.index),
+        }
+    }
+}
+
+/// Line of the byte offset in the source, starting at one.
+pub fn line_number(source: &str, offset: usize) -> usize {
+    source[..offset].matches('\n').count() + 1
+}
+
+/// Formats the number with at most four decimals, like the inspect blocks in Fancade.
+fn format_number(value: f64) -> String {
+    let formatted = format!("{:.4}", value);
+    let formatted = formatted.trim_end_matches('0').trim_end_matches('.');
+    match formatted {
+        "-0" => "0".to_string(),
+        formatted => formatted.to_string(),
+    }
+}
+
+/// Element of a variable, which are all lists of values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pointer {
+    pub variable: String,
+    pub index: usize,
+}
+
+/// Called before every node the interpreter executes.
+pub trait Hook {
+    fn before_step(&mut self, interpreter: &mut Interpreter, node: NodeId) -> Result<()>;
+}
+
+/// Executes a script graph without Fancade, one frame at a time.
+pub struct Interpreter<'a> {
+    graph: &'a Graph,
+    prefabs: &'a HashMap<String, Prefab>,
+    variables: HashMap<String, Vec<Value>>,
+    /// Outputs set by nodes when they execute, like the counter of a loop. They only last for
+    /// the frame they were set in.
+    outputs: HashMap<Endpoint, Value>,
+    frame: u64,
+    /// `late_update` nodes executed this frame, whose callbacks run after physics.
+    late: Vec<NodeId>,
+    /// Assertions executed this frame, which are checked once the frame is over.
+    asserts: Vec<NodeId>,
+    /// Nodes executed this frame, in order.
+    fired: Vec<NodeId>,
+    mocks: Mocks,
+    random: Random,
+    hits: Hits,
+    hook: Option<Box<dyn Hook + 'a>>,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(graph: &'a Graph, prefabs: &'a HashMap<String, Prefab>) -> Self {
+        Self {
+            graph,
+            prefabs,
+            variables: HashMap::new(),
+            outputs: HashMap::new(),
+            frame: 0,
+            late: Vec::new(),
+            asserts: Vec::new(),
+            fired: Vec::new(),
+            mocks: Mocks::default(),
+            random: Random::new(0),
+            hits: Hits::default(),
+            hook: None,
+        }
+    }
+
+    /// Seeds the generator behind `random`, as if `random_seed` was executed before the first
+    /// frame.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.random = Random::new(seed);
+    }
+
+    pub fn set_hook(&mut self, hook: Box<dyn Hook + 'a>) {
+        self.hook = Some(hook);
+    }
+
+    /// How often each node and callback ran over all frames.
+    pub fn hits(&self) -> &Hits {
+        &self.hits
+    }
+
+    /// Number of frames that have been run so far.
+    pub fn current_frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Runs every script once in the order they were placed, followed by the callbacks of
+    /// `late_update` and the assertions executed along the way, and returns the nodes executed
+    /// during the frame.
+    pub fn run_frame(&mut self) -> Result<Vec<NodeId>> {
+        self.outputs.clear();
+        self.mocks.touch = None;
+        self.mocks.swipe = None;
+
+        for node in 0..self.graph.nodes.len() {
+            if self.prefab(node)?.callable && self.graph.predecessors(node).is_empty() {
+                self.execute(node)?;
+            }
+        }
+
+        // There is no physics to simulate, so late updates follow right away.
+        for node in std::mem::take(&mut self.late) {
+            self.callback(node, 1)?;
+        }
+
+        for node in std::mem::take(&mut self.asserts) {
+            self.assert(node)?;
+        }
+
+        self.frame += 1;
+        Ok(std::mem::take(&mut self.fired))
+    }
+
+    /// Executes the node and every node executed after it.
+    fn execute(&mut self, node: NodeId) -> Result<()> {
+        let mut next = Some(node);
+        while let Some(node) = next {
+            self.step(node)?;
+            next = self.next(node, 0);
+        }
+        Ok(())
+    }
+
+    /// Executes the flow connected to the execute port of the node.
+    fn callback(&mut self, node: NodeId, port: usize) -> Result<()> {
+        *self.hits.callbacks.entry(Endpoint { node, port }).or_default() += 1;
+        match self.next(node, port) {
+            Some(next) => self.execute(next),
+            None => Ok(()),
+        }
+    }
+
+    fn next(&self, node: NodeId, port: usize) -> Option<NodeId> {
+        self.graph
+            .successors(node)
+            .into_iter()
+            .find(|edge| edge.from.port == port)
+            .map(|edge| edge.to.node)
+    }
+
+    fn step(&mut self, node: NodeId) -> Result<()> {
+        if let Some(mut hook) = self.hook.take() {
+            let result = hook.before_step(self, node);
+            self.hook = Some(hook);
+            result?;
+        }
+
+        let prefab = self.prefab(node)?;
+        self.fired.push(node);
+        *self.hits.nodes.entry(node).or_default() += 1;
+        match prefab.name.as_str() {
+            "inspect_number" | "inspect_vector" | "inspect_rotation" | "inspect_truth" | "inspect_object" => {
+                println!("{}", self.input(node, 0)?);
+            }
+            "set_number" | "set_vector" | "set_rotation" | "set_truth" | "set_object" | "set_constraint" => {
+                let pointer = Pointer {
+                    variable: self.name(node),
+                    index: 0,
+                };
+                let value = self.input(node, 0)?;
+                self.store(pointer, value);
+            }
+            "set_number_list" | "set_vector_list" | "set_rotation_list" | "set_truth_list"
+            | "set_object_list" | "set_constraint_list" => {
+                let pointer = self.reference(node, 0)?;
+                let value = self.input(node, 1)?;
+                if let Some(pointer) = pointer {
+                    self.store(pointer, value);
+                }
+            }
+            "increase_number" | "decrease_number" => {
+                let Some(pointer) = self.reference(node, 0)? else {
+                    return Ok(());
+                };
+                let Value::Number(value) = self.load(&pointer, RawKind::Number) else {
+                    return Err(anyhow!("`{}` can only change numbers!", prefab.name));
+                };
+                let delta = if prefab.name == "increase_number" { 1.0 } else { -1.0 };
+                self.store(pointer, Value::Number(value + delta));
+            }
+            "if" => {
+                let port = match self.input(node, 0)? {
+                    Value::Truth(true) => 1,
+                    _ => 2,
+                };
+                self.callback(node, port)?;
+            }
+            "loop" => {
+                let (Value::Number(start), Value::Number(stop)) = (self.input(node, 0)?, self.input(node, 1)?) else {
+                    return Err(anyhow!("`loop` expects numbers as start and stop!"));
+                };
+                let (start, stop) = (start.ceil() as i64, stop.ceil() as i64);
+                let counters: Box<dyn Iterator<Item = i64>> = if start <= stop {
+                    Box::new(start..stop)
+                } else {
+                    Box::new((stop + 1..=start).rev())
+                };
+                for counter in counters {
+                    self.outputs.insert(Endpoint { node, port: 0 }, Value::Number(counter as f64));
+                    self.callback(node, 1)?;
+                }
+            }
+            "random_seed" => {
+                let Value::Number(seed) = self.input(node, 0)? else {
+                    return Err(anyhow!("`random_seed` expects a number as seed!"));
+                };
+                self.random = Random::new(seed.to_bits());
+            }
+            "play_sensor" => {
+                if self.frame == 0 {
+                    self.callback(node, 1)?;
+                }
+            }
+            "late_update" => {
+                self.late.push(node);
+            }
+            "touch_sensor" => {
+                if let Some([x, y]) = self.mocks.touch {
+                    self.outputs.insert(Endpoint { node, port: 0 }, Value::Number(x));
+                    self.outputs.insert(Endpoint { node, port: 1 }, Value::Number(y));
+                    self.callback(node, 1)?;
+                }
+            }
+            "swipe_sensor" => {
+                if let Some(direction) = self.mocks.swipe {
+                    self.outputs.insert(Endpoint { node, port: 0 }, Value::Vector(direction));
+                    self.callback(node, 1)?;
+                }
+            }
+            "joystick" => {
+                self.outputs
+                    .insert(Endpoint { node, port: 0 }, Value::Vector(self.mocks.joystick));
+            }
+            // Sensors never fire without a player.
+            "button" | "collision" | "box_art_sensor" => {}
+            "mock_touch" => {
+                let (Value::Number(x), Value::Number(y)) = (self.input(node, 0)?, self.input(node, 1)?) else {
+                    return Err(anyhow!("`mock_touch` expects numbers as screen position!"));
+                };
+                self.mocks.touch = Some([x, y]);
+            }
+            "mock_swipe" | "mock_joystick" | "mock_accelerometer" => {
+                let Value::Vector(direction) = self.input(node, 0)? else {
+                    return Err(anyhow!("`{}` expects a vector as direction!", prefab.name));
+                };
+                match prefab.name.as_str() {
+                    "mock_swipe" => self.mocks.swipe = Some(direction),
+                    "mock_joystick" => self.mocks.joystick = direction,
+                    _ => self.mocks.accelerometer = direction,
+                }
+            }
+            "assert" | "assert_equal_numbers" | "assert_equal_vectors" => {
+                self.asserts.push(node);
+            }
+            "comment" => {}
+            name => return Err(anyhow!("Can't execute prefab `{}` headlessly!", name)),
+        }
+        Ok(())
+    }
+
+    /// Value going into the input of the node, dereferenced if the input takes a raw value.
+    fn input(&mut self, node: NodeId, port: usize) -> Result<Value> {
+        let kind = self.prefab(node)?.inputs[port].kind;
+        let edge = self
+            .graph
+            .inputs(node)
+            .into_iter()
+            .find(|edge| edge.to.port == port)
+            .map(|edge| edge.from);
+
+        let value = match edge {
+            Some(from) => self.output(from)?,
+            None => {
+                let (ValueKind::Raw(raw) | ValueKind::Reference(raw)) = kind;
+                Value::default(raw)
+            }
+        };
+        match (value, kind) {
+            (Value::Reference(pointer), ValueKind::Raw(raw)) => Ok(self.load(&pointer, raw)),
+            (value, _) => Ok(value),
+        }
+    }
+
+    fn is_connected(&self, node: NodeId, port: usize) -> bool {
+        self.graph.inputs(node).iter().any(|edge| edge.to.port == port)
+    }
+
+    /// Pointer going into the reference input of the node, if it is connected.
+    fn reference(&mut self, node: NodeId, port: usize) -> Result<Option<Pointer>> {
+        let edge = self
+            .graph
+            .inputs(node)
+            .into_iter()
+            .find(|edge| edge.to.port == port)
+            .map(|edge| edge.from);
+        match edge {
+            Some(from) => match self.output(from)? {
+                Value::Reference(pointer) => Ok(Some(pointer)),
+                value => Err(anyhow!("Expected a reference, found {}!", value)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn output(&mut self, endpoint: Endpoint) -> Result<Value> {
+        if let Some(value) = self.outputs.get(&endpoint) {
+            return Ok(value.clone());
+        }
+
+        let node = endpoint.node;
+        let prefab = self.prefab(node)?;
+        if prefab.is_object() {
+            return Ok(Value::Object(Some(node)));
+        }
+        // Outputs of executed nodes keep their default until the node runs.
+        if prefab.callable {
+            let (ValueKind::Raw(raw) | ValueKind::Reference(raw)) = prefab.outputs[endpoint.port].kind;
+            return Ok(Value::default(raw));
+        }
+
+        let value = match prefab.name.as_str() {
+            "number" => match self.option(node, 0) {
+                Some(&OptData::Float32(value)) => Value::Number(value as f64),
+                _ => Value::Number(0.0),
+            },
+            "vector" | "rotation" => {
+                let value = match self.option(node, 0) {
+                    Some(&OptData::Vec(value)) => value.map(|c| c as f64),
+                    _ => [0.0; 3],
+                };
+                if prefab.name == "vector" {
+                    Value::Vector(value)
+                } else {
+                    Value::Rotation(value)
+                }
+            }
+            "current_frame" => Value::Number(self.frame as f64),
+            "accelerometer" => Value::Vector(self.mocks.accelerometer),
+            "true" => Value::Truth(true),
+            "false" => Value::Truth(false),
+            "get_number" | "get_vector" | "get_rotation" | "get_truth" | "get_object" | "get_constraint" => {
+                Value::Reference(Pointer {
+                    variable: self.name(node),
+                    index: 0,
+                })
+            }
+            "list_number" | "list_vector" | "list_rotation" | "list_truth" | "list_object"
+            | "list_constraint" => {
+                let Some(pointer) = self.reference(node, 0)? else {
+                    return Err(anyhow!("`{}` needs a variable!", prefab.name));
+                };
+                let Value::Number(index) = self.input(node, 1)? else {
+                    return Err(anyhow!("`{}` expects a number as index!", prefab.name));
+                };
+                let index = pointer.index as i64 + index.floor() as i64;
+                if index < 0 {
+                    return Err(anyhow!("Index {} of `{}` is negative!", index, pointer.variable));
+                }
+                Value::Reference(Pointer {
+                    variable: pointer.variable,
+                    index: index as usize,
+                })
+            }
+            "random" => {
+                let (Value::Number(min), Value::Number(max)) = (self.input(node, 0)?, self.input(node, 1)?) else {
+                    return Err(anyhow!("`random` expects numbers as min and max!"));
+                };
+                let max = if self.is_connected(node, 1) { max } else { 1.0 };
+                // A new number is only picked once per frame.
+                let value = Value::Number(self.random.range(min, max));
+                self.outputs.insert(endpoint, value.clone());
+                value
+            }
+            "break_vector" | "break_rotation" => {
+                let (Value::Vector(value) | Value::Rotation(value)) = self.input(node, 0)? else {
+                    return Err(anyhow!("`{}` expects a vector or rotation!", prefab.name));
+                };
+                Value::Number(value[endpoint.port])
+            }
+            _ => {
+                let mut inputs = Vec::with_capacity(prefab.inputs.len());
+                for port in 0..prefab.inputs.len() {
+                    inputs.push(self.input(node, port)?);
+                }
+                evaluate(&prefab.name, &inputs)?
+            }
+        };
+        Ok(value)
+    }
+
+    fn assert(&mut self, node: NodeId) -> Result<()> {
+        let prefab = self.prefab(node)?;
+        let failure = match prefab.name.as_str() {
+            "assert" => match self.input(node, 0)? {
+                Value::Truth(true) => None,
+                _ => Some("Assertion failed".to_string()),
+            },
+            _ => {
+                let (actual, expected) = (self.input(node, 0)?, self.input(node, 1)?);
+                // Differences too small to show up in inspect blocks are ignored.
+                let equal = match (&actual, &expected) {
+                    (Value::Number(a), Value::Number(b)) => (a - b).abs() < 0.0001,
+                    (Value::Vector(a), Value::Vector(b)) => a.iter().zip(b).all(|(a, b)| (a - b).abs() < 0.0001),
+                    _ => actual == expected,
+                };
+                (!equal).then(|| format!("Expected {}, found {}", expected, actual))
+            }
+        };
+
+        let Some(failure) = failure else {
+            return Ok(());
+        };
+        let message = match self.option(node, 0) {
+            Some(OptData::Name(message)) => format!("{}: {}", failure, message),
+            _ => failure,
+        };
+        Err(AssertionFailed {
+            message,
+            span: self.graph.nodes[node].span.clone(),
+        }
+        .into())
+    }
+
+    fn load(&self, pointer: &Pointer, kind: RawKind) -> Value {
+        self.variables
+            .get(&pointer.variable)
+            .and_then(|values| values.get(pointer.index))
+            .cloned()
+            .unwrap_or_else(|| Value::default(kind))
+    }
+
+    fn store(&mut self, pointer: Pointer, value: Value) {
+        let values = self.variables.entry(pointer.variable).or_default();
+        if values.len() <= pointer.index {
+            values.resize(pointer.index + 1, value.clone());
+        }
+        values[pointer.index] = value;
+    }
+
+    fn option(&self, node: NodeId, index: u8) -> Option<&'a OptData> {
+        self.graph.nodes[node]
+            .options
+            .iter()
+            .find(|(i, _)| *i == index)
+            .map(|(_, data)| data)
+    }
+
+    /// Name of the variable a node refers to.
+    fn name(&self, node: NodeId) -> String {
+        match self.option(node, 0) {
+            Some(OptData::Name(name)) => name.clone(),
+            _ => String::new(),
+        }
+    }
+
+    fn prefab(&self, node: NodeId) -> Result<&'a Prefab> {
+        let name = &self.graph.nodes[node].prefab;
+        self.prefabs
+            .get(name)
+            .ok_or_else(|| anyhow!("Can't find prefab with name: {}", name))
+    }
+}
+
+/// Evaluates a math prefab, falling back to the constant folder for the ones it knows.
+fn evaluate(name: &str, inputs: &[Value]) -> Result<Value> {
+    use Value::*;
+
+    let value = match (name, inputs) {
+        ("modulo", &[Number(a), Number(b)]) => Number(a.rem_euclid(b)),
+        ("round", &[Number(a)]) => Number(a.round()),
+        ("divide", &[Number(a), Number(b)]) => Number(a / b),
+        ("equal_vectors", &[Vector(a), Vector(b)]) => Truth(a == b),
+        ("equal_objects", [Object(a), Object(b)]) => Truth(a == b),
+        ("normalize", &[Vector(a)]) => Vector(math::normalize(a)),
+        ("rotate", &[Vector(v), Rotation(r)]) => Vector(math::rotate(math::from_euler(r), v)),
+        ("combine", &[Rotation(a), Rotation(b)]) => {
+            Rotation(math::to_euler(math::multiply(math::from_euler(a), math::from_euler(b))))
+        }
+        ("inverse", &[Rotation(r)]) => Rotation(math::to_euler(math::inverse(math::from_euler(r)))),
+        ("lerp", &[Rotation(a), Rotation(b), Number(amount)]) => Rotation(math::to_euler(math::slerp(
+            math::from_euler(a),
+            math::from_euler(b),
+            amount,
+        ))),
+        ("axis_angle", &[Vector(axis), Number(angle)]) => Rotation(math::to_euler(math::axis_angle(axis, angle))),
+        ("look_rotation", &[Vector(direction), Vector(up)]) => {
+            Rotation(math::to_euler(math::look_rotation(direction, up)))
+        }
+        _ => {
+            let constants = inputs.iter().map(Value::constant).collect::<Option<Vec<_>>>();
+            match constants.and_then(|constants| fold::evaluate(name, &constants)) {
+                Some(constant) => Value::from_constant(constant),
+                None => return Err(anyhow!("Can't evaluate prefab `{}` headlessly!", name)),
+            }
+        }
+    };
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{Interpreter, Value};
+    use crate::{
+        parser::parse_source,
+        transpiler::{build_graph, prefabs::get_prefabs},
+    };
+
+    /// Runs the script for the number of frames, returning the values of its variables.
+    fn run(source: &str, frames: u64) -> HashMap<String, Vec<Value>> {
+        let prefabs = get_prefabs();
+        let graph = build_graph(parse_source(source).unwrap(), &prefabs, &mut Vec::new()).unwrap();
+        let mut interpreter = Interpreter::new(&graph, &prefabs);
+        for _ in 0..frames {
+            interpreter.run_frame().unwrap();
+        }
+        interpreter.variables
+    }
+
+    fn value<'a>(variables: &'a HashMap<String, Vec<Value>>, name: &str) -> &'a Value {
+        &variables[name][0]
+    }
+
+    #[test]
+    fn branches_and_loops() {
+        let variables = run(
+            "$sum = 0
+            for i in 0..4 {
+                $sum += i
+            }
+            if $sum > 10 {
+                $branch = 1
+            } else if $sum > 5 {
+                $branch = 2
+            } else {
+                $branch = 3
+            }
+            $energy = 0
+            while $energy < 100 {
+                $energy += 30
+            }",
+            1,
+        );
+        assert_eq!(value(&variables, "$sum"), &Value::Number(6.0));
+        assert_eq!(value(&variables, "$branch"), &Value::Number(2.0));
+        assert_eq!(value(&variables, "$energy"), &Value::Number(120.0));
+    }
+
+    #[test]
+    fn stops_while_loops_once_the_condition_fails() {
+        let prefabs = get_prefabs();
+        let source = "$n = 0\nwhile $n < 5 {\n  $n += 1\n}";
+        let graph = build_graph(parse_source(source).unwrap(), &prefabs, &mut Vec::new()).unwrap();
+        let mut interpreter = Interpreter::new(&graph, &prefabs);
+        let fired = interpreter.run_frame().unwrap();
+        assert_eq!(value(&interpreter.variables, "$n"), &Value::Number(5.0));
+        // One pass of the inner loop runs out after the condition fails, the rest never start.
+        assert!(fired.len() < 100, "{} nodes were executed", fired.len());
+
+        let variables = run("$n = 0\nwhile True {\n  $n += 1\n}", 1);
+        assert_eq!(value(&variables, "$n"), &Value::Number(1024.0));
+    }
+
+    #[test]
+    fn compares_values() {
+        let variables = run(
+            "$x = 2
+            $at_most = $x <= 2
+            $at_least = $x >= 3
+            $equal = $x == 2
+            $not_equal = vec($x, 0, 0) != vec(2, 0, 0)",
+            1,
+        );
+        assert_eq!(value(&variables, "$at_most"), &Value::Truth(true));
+        assert_eq!(value(&variables, "$at_least"), &Value::Truth(false));
+        assert_eq!(value(&variables, "$equal"), &Value::Truth(true));
+        assert_eq!(value(&variables, "$not_equal"), &Value::Truth(false));
+    }
+
+    #[test]
+    fn inlines_definitions() {
+        let variables = run(
+            "def lerp(from, to, amount) |result| {
+              result = from + (to - from) * amount
+            }
+            def check(value) small, large {
+              if value < 5 {
+                small()
+              } else {
+                large()
+              }
+            }
+            $value = lerp(4, 8, 0.5)
+            check($value) small {
+              $large = False
+            } large {
+              $large = True
+            }",
+            1,
+        );
+        assert_eq!(value(&variables, "$value"), &Value::Number(6.0));
+        assert_eq!(value(&variables, "$large"), &Value::Truth(true));
+    }
+}
@@ -0,0 +1,44 @@
+/// Seedable generator standing in for the random numbers of Fancade, so that runs can be
+/// reproduced.
+#[derive(Debug, Clone)]
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Next number in `[0, 1)`, generated with SplitMix64.
+    pub fn next_f64(&mut self) -> f64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Next number between min and max, excluding max.
+    pub fn range(&mut self, min: f64, max: f64) -> f64 {
+        min + self.next_f64() * (max - min)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Random;
+
+    fn sequence(seed: u64) -> Vec<f64> {
+        let mut random = Random::new(seed);
+        (0..8).map(|_| random.range(-10.0, 10.0)).collect()
+    }
+
+    #[test]
+    fn repeats_sequences_of_a_seed() {
+        assert_eq!(sequence(42), sequence(42));
+        assert_ne!(sequence(42), sequence(43));
+        assert!(sequence(42).iter().all(|value| (-10.0..10.0).contains(value)));
+    }
+}
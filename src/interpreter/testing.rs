@@ -0,0 +1,210 @@
+use anyhow::Result;
+use std::{collections::HashMap, error::Error, fmt::Display};
+
+use crate::{
+    game::{OptKind, RawKind, ValueKind},
+    interpreter::{coverage::Report, Interpreter},
+    parser::grammar::{Span, Statement},
+    transpiler::{
+        build_graph,
+        prefabs::{Opt, Prefab, ValuePort},
+        Warning,
+    },
+};
+
+/// Splits the statements of a script into its `test_*` definitions and the rest of the script.
+pub fn discover_tests(statements: Vec<Statement>) -> (Vec<Statement>, Vec<Statement>) {
+    statements.into_iter().partition(
+        |statement| matches!(statement, Statement::Definition { name, .. } if name.starts_with("test_")),
+    )
+}
+
+/// Runs the body of a test in front of the script for the given number of frames, so that
+/// mocked input reaches the script in the same frame while assertions are checked once the frame
+/// is over. Hits are added to the report even if the test fails.
+pub fn run_test(
+    body: Vec<Statement>,
+    script: &[Statement],
+    prefabs: &HashMap<String, Prefab>,
+    frames: u64,
+    seed: u64,
+    report: &mut Report,
+    warnings: &mut Vec<Warning>,
+) -> Result<()> {
+    let statements = body.into_iter().chain(script.iter().cloned()).collect();
+    let graph = build_graph(statements, prefabs, warnings)?;
+
+    let mut interpreter = Interpreter::new(&graph, prefabs);
+    interpreter.set_seed(seed);
+    let mut result = Ok(());
+    for _ in 0..frames {
+        result = interpreter.run_frame().map(|_| ());
+        if result.is_err() {
+            break;
+        }
+    }
+
+    report.record(&graph, prefabs, interpreter.hits());
+    result
+}
+
+/// Sensor input scripted by a test. Touches and swipes only last for the frame they are mocked
+/// in, while the joystick and accelerometer keep their direction until they are mocked again.
+#[derive(Debug, Default)]
+pub struct Mocks {
+    pub touch: Option<[f64; 2]>,
+    pub swipe: Option<[f64; 3]>,
+    pub joystick: [f64; 3],
+    pub accelerometer: [f64; 3],
+}
+
+/// Raised when an assertion of a test doesn't hold.
+#[derive(Debug)]
+pub struct AssertionFailed {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl Display for AssertionFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for AssertionFailed {}
+
+/// Prefabs only available to tests, which have no blocks in Fancade.
+pub fn test_prefabs() -> Vec<Prefab> {
+    let value = |name: &str, kind: RawKind| ValuePort {
+        name: name.to_string(),
+        kind: ValueKind::Raw(kind),
+    };
+    let message = || Opt {
+        name: "message".to_string(),
+        kind: OptKind::Name,
+    };
+
+    vec![
+        Prefab {
+            name: "assert".to_string(),
+            inputs: vec![value("condition", RawKind::Truth)],
+            options: vec![message()],
+            callable: true,
+            ..Default::default()
+        },
+        Prefab {
+            name: "assert_equal_numbers".to_string(),
+            inputs: vec![value("actual", RawKind::Number), value("expected", RawKind::Number)],
+            options: vec![message()],
+            callable: true,
+            ..Default::default()
+        },
+        Prefab {
+            name: "assert_equal_vectors".to_string(),
+            inputs: vec![value("actual", RawKind::Vector), value("expected", RawKind::Vector)],
+            options: vec![message()],
+            callable: true,
+            ..Default::default()
+        },
+        Prefab {
+            name: "mock_touch".to_string(),
+            inputs: vec![value("screen_x", RawKind::Number), value("screen_y", RawKind::Number)],
+            callable: true,
+            ..Default::default()
+        },
+        Prefab {
+            name: "mock_swipe".to_string(),
+            inputs: vec![value("direction", RawKind::Vector)],
+            callable: true,
+            ..Default::default()
+        },
+        Prefab {
+            name: "mock_joystick".to_string(),
+            inputs: vec![value("direction", RawKind::Vector)],
+            callable: true,
+            ..Default::default()
+        },
+        Prefab {
+            name: "mock_accelerometer".to_string(),
+            inputs: vec![value("direction", RawKind::Vector)],
+            callable: true,
+            ..Default::default()
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::parse_source, transpiler::prefabs::get_prefabs};
+
+    /// Runs every test of the script, returning the names of those that failed.
+    fn failures(source: &str, frames: u64) -> Vec<String> {
+        let mut prefabs = get_prefabs();
+        for prefab in test_prefabs() {
+            prefabs.insert(prefab.name.clone(), prefab);
+        }
+        let statements = parse_source(source).unwrap();
+        let mut report = Report::new(source, &statements);
+        let (tests, script) = discover_tests(statements);
+
+        let mut failed = Vec::new();
+        for test in tests {
+            let Statement::Definition { name, statements, .. } = test else {
+                unreachable!();
+            };
+            if run_test(statements, &script, &prefabs, frames, 0, &mut report, &mut Vec::new()).is_err() {
+                failed.push(name);
+            }
+        }
+        failed
+    }
+
+    #[test]
+    fn checks_assertions_after_the_script() {
+        let source = r#"
+            on touch |x, y| {
+                $score = $score + x + y
+            }
+
+            def test_touch_scores() {
+                mock_touch(10, 20)
+                assert_equal_numbers($score, 30, "touch adds its position")
+            }
+
+            def test_touch_misses() {
+                mock_touch(1, 2)
+                assert_equal_numbers($score, 30)
+            }
+        "#;
+        assert_eq!(failures(source, 1), ["test_touch_misses"]);
+    }
+
+    #[test]
+    fn scripts_input_over_several_frames() {
+        let source = r#"
+            on touch |x, y| {
+                $touches = $touches + 1
+            }
+            direction = joystick()
+            $steps = $steps + direction.x
+
+            def test_touches_once() {
+                if current_frame() == 1 {
+                    mock_touch(0, 0)
+                }
+                if current_frame() == 3 {
+                    assert_equal_numbers($touches, 1)
+                }
+            }
+
+            def test_holds_joystick() {
+                if current_frame() == 0 {
+                    mock_joystick(vec(1, 0, 0))
+                }
+                assert_equal_numbers($steps, current_frame() + 1)
+            }
+        "#;
+        assert!(failures(source, 4).is_empty());
+    }
+}
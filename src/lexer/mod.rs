@@ -1,35 +1,62 @@
 use token::*;
 use winnow::{
-    ascii::{digit1, multispace0},
-    combinator::{alt, delimited, dispatch, eof, opt, peek, preceded, repeat, terminated},
-    stream::AsChar,
-    token::{any, take_while},
-    Parser, Result,
+    ascii::{digit1, multispace1},
+    combinator::{alt, cut_err, delimited, dispatch, eof, fail, not, opt, peek, preceded, repeat},
+    error::{ContextError, ErrMode, StrContext, StrContextValue},
+    stream::{AsChar, LocatingSlice},
+    token::{any, one_of, take_while},
+    ModalResult, Parser,
 };
 
 pub mod token;
 
-pub fn token<'s>(i: &mut &'s str) -> Result<Token<'s>> {
+pub type Source<'s> = LocatingSlice<&'s str>;
+
+/// The name of a global or saved variable, which has to directly follow its prefix.
+fn variable_name<'s>(i: &mut Source<'s>) -> ModalResult<&'s str> {
+    (one_of(AsChar::is_alpha), take_while(.., |char: char| char.is_ascii_alphanumeric() || char == '_'))
+        .take()
+        .parse_next(i)
+}
+
+/// Fails lexing for good, since a variable prefix can't stand on its own.
+fn misplaced_prefix<'s>(expected: &'static str) -> impl Parser<Source<'s>, Kind, ErrMode<ContextError>> {
+    cut_err(fail).context(StrContext::Expected(StrContextValue::Description(expected)))
+}
+
+/// An arithmetic operator, or its compound assignement when followed by `=`.
+fn compound<'s>(operator: Operator) -> impl Parser<Source<'s>, Kind, ErrMode<ContextError>> {
+    preceded(any, opt('=')).map(move |v| match v {
+        Some(_) => Kind::CompoundAssignement(operator),
+        None => Kind::Operator(operator),
+    })
+}
+
+pub fn token<'s>(i: &mut Source<'s>) -> ModalResult<Token<'s>> {
     dispatch!{peek(any);
         '0'..='9' | '.' => alt((
             (digit1, '.', digit1).take().value(Kind::Float),
-            (digit1, '.').take().value(Kind::Float),
+            // A dot right after the number starts a range like `0..10` instead.
+            (digit1, '.', not('.')).take().value(Kind::Float),
             ('.', digit1).take().value(Kind::Float),
             (digit1).take().value(Kind::Integer),
+            "..".value(Kind::Range),
             '.'.value(Kind::Dot),
         )),
         '(' => any.value(Kind::Parenthesis(Handedness::Opening)),
         ')' => any.value(Kind::Parenthesis(Handedness::Closing)),
         '{' => any.value(Kind::Bracket(Handedness::Opening)),
         '}' => any.value(Kind::Bracket(Handedness::Closing)),
+        '[' => any.value(Kind::SquareBracket(Handedness::Opening)),
+        ']' => any.value(Kind::SquareBracket(Handedness::Closing)),
         '|' => any.value(Kind::Pipe),
-        '+' => any.value(Kind::Operator(Operator::Add)),
-        '-' => any.value(Kind::Operator(Operator::Subtract)),
-        '*' => preceded(any, opt('*')).map(|v| Kind::Operator(match v {
-            Some(_) => Operator::Power,
-            None => Operator::Multiply,
-        })),
-        '/' => any.value(Kind::Operator(Operator::Divide)),
+        '+' => compound(Operator::Add),
+        '-' => compound(Operator::Subtract),
+        '*' => alt((
+            "**".value(Kind::Operator(Operator::Power)),
+            compound(Operator::Multiply),
+        )),
+        '/' => compound(Operator::Divide),
         '=' => preceded(any, opt('=')).map(|v| match v {
             Some(_) => Kind::Operator(Operator::Equal),
             None => Kind::Assignement
@@ -42,11 +69,15 @@ pub fn token<'s>(i: &mut &'s str) -> Result<Token<'s>> {
             Some(_) => Operator::AtLeast,
             None => Operator::GreaterThan,
         })),
-        '!' => preceded(any, opt('=')).map(|v| match v {
-            Some(_) => Kind::Operator(Operator::NotEqual),
-            None => Kind::Modifier,
-        }),
-        '$' => any.value(Kind::Modifier),
+        '!' => alt((
+            "!=".value(Kind::Operator(Operator::NotEqual)),
+            ('!', variable_name).value(Kind::SavedVariable),
+            misplaced_prefix("a name right after `!` for a saved variable, like `!best`, or `!=`"),
+        )),
+        '$' => alt((
+            ('$', variable_name).value(Kind::GlobalVariable),
+            misplaced_prefix("a name right after `$` for a global variable, like `$score`"),
+        )),
         '&' => any.value(Kind::Reference),
         'a'..='z' | 'A'..='Z' => take_while(1.., |char: char| char.is_ascii_alphanumeric() || char == '_').map(|v| match v {
             "not" => Kind::Operator(Operator::Not),
@@ -60,21 +91,24 @@ pub fn token<'s>(i: &mut &'s str) -> Result<Token<'s>> {
         '_' => any.value(Kind::Skip),
         ',' => any.value(Kind::Comma),
         ':' => any.value(Kind::Label),
+        ' ' | '\t' | '\r' | '\n' => multispace1.value(Kind::Whitespace),
         '#' => preceded('#', take_while(.., |char: char| !char.is_newline())).value(Kind::Comment),
         _ => take_while(.., |char: char| !char.is_ascii_whitespace()).value(Kind::Unknown)
     }
     .with_taken()
-    .map(|(kind, value)| Token { kind, value})
+    .with_span()
+    .map(|((kind, value), span)| Token { kind, value, offset: span.start })
     .parse_next(i)
 }
 
-pub fn tokens<'s>(i: &mut &'s str) -> Result<Vec<Token<'s>>> {
-    let mut tokens: Vec<_> =
-        preceded(multispace0, repeat(1.., terminated(token, multispace0))).parse_next(i)?;
+/// Lexes the source into tokens that add up to the whole source, including whitespace.
+pub fn lossless_tokens<'s>(i: &mut Source<'s>) -> ModalResult<Vec<Token<'s>>> {
+    let mut tokens: Vec<_> = repeat(.., token).parse_next(i)?;
 
-    let eof = opt(eof.map(|value| Token {
+    let eof = opt(eof.with_span().map(|(value, span)| Token {
         kind: Kind::EndOfFile,
         value,
+        offset: span.start,
     }))
     .parse_next(i)?;
     if let Some(eof) = eof {
@@ -83,3 +117,8 @@ pub fn tokens<'s>(i: &mut &'s str) -> Result<Vec<Token<'s>>> {
 
     Ok(tokens)
 }
+
+pub fn tokens<'s>(i: &mut Source<'s>) -> ModalResult<Vec<Token<'s>>> {
+    let tokens = lossless_tokens(i)?;
+    Ok(tokens.into_iter().filter(|token| token.kind != Kind::Whitespace).collect())
+}
@@ -2,7 +2,7 @@ use std::fmt::{self, Debug};
 
 use winnow::{
     error::ContextError,
-    stream::{ContainsToken, TokenSlice},
+    stream::{ContainsToken, Location, TokenSlice},
     token::literal,
     Parser, Result,
 };
@@ -11,6 +11,7 @@ use winnow::{
 pub struct Token<'s> {
     pub kind: Kind,
     pub value: &'s str,
+    pub offset: usize,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -18,14 +19,19 @@ pub enum Kind {
     Operator(Operator),
     Parenthesis(Handedness),
     Bracket(Handedness),
+    SquareBracket(Handedness),
     Pipe,
     Comma,
     Dot,
+    Range,
     Name,
-    Modifier,
+    GlobalVariable,
+    SavedVariable,
     Reference,
     Label,
     Assignement,
+    /// Assignement combined with an arithmetic operator, like `+=`.
+    CompoundAssignement(Operator),
     Definition,
     Float,
     Integer,
@@ -33,6 +39,7 @@ pub enum Kind {
     String,
     Skip,
     Comment,
+    Whitespace,
     Unknown,
     EndOfFile,
 }
@@ -61,12 +68,29 @@ pub enum Handedness {
     Closing,
 }
 
+impl Token<'_> {
+    /// Whether the token only matters for the layout of the source.
+    pub fn is_trivia(&self) -> bool {
+        matches!(self.kind, Kind::Whitespace | Kind::Comment)
+    }
+}
+
 impl PartialEq<Kind> for Token<'_> {
     fn eq(&self, other: &Kind) -> bool {
         self.kind == *other
     }
 }
 
+impl Location for Token<'_> {
+    fn previous_token_end(&self) -> usize {
+        self.offset + self.value.len()
+    }
+
+    fn current_token_start(&self) -> usize {
+        self.offset
+    }
+}
+
 pub(crate) type Tokens<'i> = TokenSlice<'i, Token<'i>>;
 
 impl<'i> Parser<Tokens<'i>, &'i Token<'i>, ContextError> for Kind {
@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+
+use crate::{
+    game::{RawKind, ValueKind},
+    parser::{
+        self,
+        grammar::{Callback, Expression, Span, Statement},
+    },
+    transpiler::{build_graph, prefabs::Prefab, TranspileError},
+};
+
+/// How severe a diagnostic is, numbered like the protocol.
+#[derive(Debug, Clone, Copy)]
+pub enum Severity {
+    Error = 1,
+    Warning = 2,
+}
+
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+}
+
+/// Checks a script by parsing and lowering it, collecting syntax errors and transpiler warnings.
+/// Problems that aren't tied to a location are put at the start of the script.
+pub fn diagnose(source: &str, prefabs: &HashMap<String, Prefab>) -> Vec<Diagnostic> {
+    let statements = match parser::parse_source(source) {
+        Ok(statements) => statements,
+        Err(error) => {
+            return vec![Diagnostic {
+                severity: Severity::Error,
+                message: error.message,
+                span: error.span,
+            }]
+        }
+    };
+
+    let mut warnings = Vec::new();
+    let result = build_graph(statements, prefabs, &mut warnings);
+
+    let mut diagnostics = warnings
+        .into_iter()
+        .map(|warning| Diagnostic {
+            severity: Severity::Warning,
+            message: warning.message,
+            span: warning.span.unwrap_or(0..0),
+        })
+        .collect_vec();
+    if let Err(error) = result {
+        let span = error
+            .downcast_ref::<TranspileError>()
+            .and_then(|error| error.span.clone());
+        diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            message: error.to_string(),
+            span: span.unwrap_or(0..0),
+        });
+    }
+    diagnostics
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymbolKind {
+    Definition,
+    Label,
+}
+
+/// A name introduced by a script.
+#[derive(Debug)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// What the symbol is, for example the kind of value a label holds.
+    pub detail: Option<String>,
+    /// The statement introducing the symbol.
+    pub span: Span,
+    /// The name within the statement.
+    pub name_span: Span,
+}
+
+/// Collects the definitions and labels of a script in source order.
+pub fn symbols(
+    source: &str,
+    statements: &[Statement],
+    prefabs: &HashMap<String, Prefab>,
+) -> Vec<Symbol> {
+    let mut symbols = Vec::new();
+    collect_symbols(source, statements, prefabs, &mut symbols);
+    symbols
+}
+
+fn collect_symbols(
+    source: &str,
+    statements: &[Statement],
+    prefabs: &HashMap<String, Prefab>,
+    symbols: &mut Vec<Symbol>,
+) {
+    let symbol = |name: &str, kind, detail, span: &Span| Symbol {
+        name: name.to_string(),
+        kind,
+        detail,
+        span: span.clone(),
+        name_span: find_name(source, span, name),
+    };
+
+    for statement in statements {
+        match statement {
+            Statement::Invocation {
+                name,
+                outputs,
+                callbacks,
+                span,
+                ..
+            } => {
+                let prefab = prefabs.get(name);
+                for (port, output) in outputs.iter().enumerate() {
+                    if let (None, Some(label), false) = (&output.modifier, &output.name, output.is_indirect()) {
+                        symbols.push(symbol(
+                            label,
+                            SymbolKind::Label,
+                            output_kind(prefab, port).map(|kind| format_kind(&kind)),
+                            span,
+                        ));
+                    }
+                }
+                for Callback { outputs, .. } in callbacks {
+                    for (port, output) in outputs.iter().enumerate() {
+                        if let (None, Some(label), false) = (&output.modifier, &output.name, output.is_indirect()) {
+                            symbols.push(symbol(
+                                label,
+                                SymbolKind::Label,
+                                output_kind(prefab, port).map(|kind| format_kind(&kind)),
+                                span,
+                            ));
+                        }
+                    }
+                }
+            }
+            Statement::Assignement {
+                value,
+                outputs,
+                span,
+            } => {
+                for (port, output) in outputs.iter().enumerate() {
+                    let (None, Some(label), false) = (&output.modifier, &output.name, output.is_indirect()) else {
+                        continue;
+                    };
+                    let kind = match value {
+                        Expression::Call { name, .. } | Expression::Method { name, .. } => {
+                            output_kind(prefabs.get(name), port)
+                        }
+                        Expression::Float(_) | Expression::Integer(_) => {
+                            Some(ValueKind::Raw(RawKind::Number))
+                        }
+                        Expression::Boolean(_) => Some(ValueKind::Raw(RawKind::Truth)),
+                        Expression::Vector(_) => Some(ValueKind::Raw(RawKind::Vector)),
+                        Expression::Rotation(_) => Some(ValueKind::Raw(RawKind::Rotation)),
+                        _ => None,
+                    }
+                    .map(|kind| format_kind(&kind));
+                    symbols.push(symbol(label, SymbolKind::Label, kind, span));
+                }
+            }
+            Statement::Definition {
+                name,
+                inputs,
+                outputs,
+                callbacks,
+                statements: body,
+                span,
+            } => {
+                let signature = format_definition(name, inputs, callbacks, outputs);
+                symbols.push(symbol(name, SymbolKind::Definition, Some(signature), span));
+                for label in inputs.iter().chain(outputs) {
+                    symbols.push(symbol(label, SymbolKind::Label, None, span));
+                }
+                collect_symbols(source, body, prefabs, symbols);
+                continue;
+            }
+            Statement::While { statements, .. } => collect_symbols(source, statements, prefabs, symbols),
+            Statement::Comment(_) => {}
+        }
+
+        if let Statement::Invocation { callbacks, .. } = statement {
+            for callback in callbacks {
+                collect_symbols(source, &callback.statements, prefabs, symbols);
+            }
+        }
+    }
+}
+
+fn output_kind(prefab: Option<&Prefab>, port: usize) -> Option<ValueKind> {
+    Some(prefab?.outputs.get(port)?.kind)
+}
+
+/// Finds the first occurrence of a name as a whole word within the span, falling back to the
+/// whole span.
+fn find_name(source: &str, span: &Span, name: &str) -> Span {
+    let text = source.get(span.clone()).unwrap_or_default();
+    text.match_indices(name)
+        .find(|(start, _)| {
+            let before = text[..*start].chars().next_back();
+            let after = text[start + name.len()..].chars().next();
+            !before.is_some_and(is_name_char) && !after.is_some_and(is_name_char)
+        })
+        .map(|(start, _)| span.start + start..span.start + start + name.len())
+        .unwrap_or(span.clone())
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Finds the name under a byte offset of the source.
+pub fn name_at(source: &str, offset: usize) -> Option<(&str, Span)> {
+    let offset = offset.min(source.len());
+    let start = source[..offset]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_name_char(*c))
+        .last()
+        .map_or(offset, |(i, _)| i);
+    let end = source[offset..]
+        .char_indices()
+        .find(|(_, c)| !is_name_char(*c))
+        .map_or(source.len(), |(i, _)| offset + i);
+    (start < end).then(|| (&source[start..end], start..end))
+}
+
+pub fn format_kind(kind: &ValueKind) -> String {
+    format!("{:?}", kind)
+}
+
+pub fn format_definition(
+    name: &str,
+    inputs: &[String],
+    callbacks: &[String],
+    outputs: &[String],
+) -> String {
+    let mut signature = format!("def {}({})", name, inputs.join(", "));
+    if !callbacks.is_empty() {
+        signature += &format!(" {}", callbacks.join(", "));
+    }
+    if !outputs.is_empty() {
+        signature += &format!(" |{}|", outputs.join(", "));
+    }
+    signature
+}
+
+/// A one line summary of the ports of a prefab.
+pub fn prefab_signature(prefab: &Prefab) -> String {
+    let inputs = prefab.inputs.iter().map(|input| &input.name).join(", ");
+    let mut signature = format!("{}({})", prefab.name, inputs);
+    if !prefab.callbacks.is_empty() {
+        signature += &format!(
+            " {}",
+            prefab
+                .callbacks
+                .iter()
+                .map(|callback| &callback.name)
+                .join(", ")
+        );
+    }
+    if !prefab.outputs.is_empty() {
+        signature += &format!(
+            " |{}|",
+            prefab.outputs.iter().map(|output| &output.name).join(", ")
+        );
+    }
+    signature
+}
+
+/// Documents the inputs, outputs, options and callbacks of a prefab in markdown.
+pub fn prefab_documentation(prefab: &Prefab) -> String {
+    let mut documentation = format!("```\n{}\n```\n", prefab_signature(prefab));
+    let mut section = |title: &str, lines: Vec<String>| {
+        if !lines.is_empty() {
+            documentation += &format!("\n**{}**\n{}\n", title, lines.join("\n"));
+        }
+    };
+
+    let ports = |ports: &[crate::transpiler::prefabs::ValuePort]| {
+        ports
+            .iter()
+            .map(|port| format!("- `{}`: `{}`", port.name, format_kind(&port.kind)))
+            .collect_vec()
+    };
+    section("inputs", ports(&prefab.inputs));
+    section("outputs", ports(&prefab.outputs));
+    section(
+        "options",
+        prefab
+            .options
+            .iter()
+            .map(|opt| format!("- `{}`: `{:?}`", opt.name, opt.kind))
+            .collect(),
+    );
+    section(
+        "callbacks",
+        prefab
+            .callbacks
+            .iter()
+            .map(|callback| format!("- `{}`", callback.name))
+            .collect(),
+    );
+    documentation
+}
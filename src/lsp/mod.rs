@@ -0,0 +1,476 @@
+use anyhow::{anyhow, Result};
+use itertools::Itertools;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::Display,
+    io::{stdin, stdout, BufRead, Write},
+};
+
+use crate::{
+    parser::{self, grammar::Span},
+    transpiler::prefabs::{get_prefabs, Prefab},
+};
+use analysis::{
+    diagnose, name_at, prefab_documentation, prefab_signature, symbols, Symbol, SymbolKind,
+};
+
+mod analysis;
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const REQUEST_FAILED: i64 = -32803;
+
+/// Raised for methods the server doesn't implement.
+#[derive(Debug)]
+struct UnknownMethod(String);
+
+impl Display for UnknownMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unknown method: {}", self.0)
+    }
+}
+
+impl Error for UnknownMethod {}
+
+/// A language server for scripts speaking JSON-RPC over stdin and stdout.
+pub struct Server<W: Write> {
+    writer: W,
+    prefabs: HashMap<String, Prefab>,
+    /// Contents of the open documents, keyed by uri.
+    documents: HashMap<String, String>,
+}
+
+/// Serves the language server until the client exits.
+pub fn serve() -> Result<()> {
+    let mut server = Server::new(stdout().lock());
+    server.run(&mut stdin().lock())
+}
+
+impl<W: Write> Server<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            prefabs: get_prefabs(),
+            documents: HashMap::new(),
+        }
+    }
+
+    pub fn run(&mut self, reader: &mut impl BufRead) -> Result<()> {
+        while let Some(message) = read_message(reader)? {
+            let id = message.get("id").cloned();
+            let Some(method) = message["method"].as_str() else {
+                // Responses to requests of the server, which never makes any.
+                continue;
+            };
+            if method == "exit" {
+                return Ok(());
+            }
+
+            // A message that can't be handled is answered or logged, but never stops the server.
+            match (self.handle(method, &message["params"]), id) {
+                (Ok(Some(result)), Some(id)) => {
+                    self.send(json!({ "jsonrpc": "2.0", "id": id, "result": result }))?;
+                }
+                (Ok(_), _) => {}
+                (Err(error), Some(id)) => {
+                    let code = match error.is::<UnknownMethod>() {
+                        true => METHOD_NOT_FOUND,
+                        false => REQUEST_FAILED,
+                    };
+                    self.send(json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": code, "message": error.to_string() },
+                    }))?;
+                }
+                // Clients send notifications the server doesn't need, like `initialized`.
+                (Err(error), None) if error.is::<UnknownMethod>() => {}
+                (Err(error), None) => {
+                    self.notify(
+                        "window/logMessage",
+                        json!({ "type": 1, "message": format!("{} failed: {}", method, error) }),
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles a request or notification, returning the result to respond to requests with.
+    fn handle(&mut self, method: &str, params: &Value) -> Result<Option<Value>> {
+        Ok(match method {
+            "initialize" => Some(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "completionProvider": {},
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+            })),
+            "shutdown" => Some(Value::Null),
+            "textDocument/didOpen" => {
+                let document = &params["textDocument"];
+                let uri = string(&document["uri"])?;
+                self.documents
+                    .insert(uri.clone(), string(&document["text"])?);
+                self.publish_diagnostics(&uri)?;
+                None
+            }
+            "textDocument/didChange" => {
+                let uri = string(&params["textDocument"]["uri"])?;
+                // Documents are synced in full, so the last change holds the whole text.
+                if let Some(change) = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                {
+                    self.documents.insert(uri.clone(), string(&change["text"])?);
+                }
+                self.publish_diagnostics(&uri)?;
+                None
+            }
+            "textDocument/didClose" => {
+                let uri = string(&params["textDocument"]["uri"])?;
+                self.documents.remove(&uri);
+                self.notify(
+                    "textDocument/publishDiagnostics",
+                    json!({ "uri": uri, "diagnostics": [] }),
+                )?;
+                None
+            }
+            "textDocument/completion" => Some(self.completion(params)?),
+            "textDocument/hover" => Some(self.hover(params)?),
+            "textDocument/definition" => Some(self.definition(params)?),
+            "textDocument/documentSymbol" => Some(self.document_symbols(params)?),
+            _ => return Err(UnknownMethod(method.to_string()).into()),
+        })
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str) -> Result<()> {
+        let source = self.document(uri)?;
+        let diagnostics = diagnose(source, &self.prefabs)
+            .into_iter()
+            .map(|diagnostic| {
+                json!({
+                    "range": range(source, &diagnostic.span),
+                    "severity": diagnostic.severity as u8,
+                    "source": env!("CARGO_PKG_NAME"),
+                    "message": diagnostic.message,
+                })
+            })
+            .collect_vec();
+        self.notify(
+            "textDocument/publishDiagnostics",
+            json!({ "uri": uri, "diagnostics": diagnostics }),
+        )
+    }
+
+    fn completion(&self, params: &Value) -> Result<Value> {
+        let uri = string(&params["textDocument"]["uri"])?;
+        let source = self.document(&uri)?;
+
+        let prefabs = self
+            .prefabs
+            .values()
+            .sorted_by_key(|prefab| &prefab.name)
+            .map(|prefab| {
+                json!({
+                    "label": prefab.name,
+                    "kind": 3,
+                    "detail": prefab_signature(prefab),
+                    "documentation": { "kind": "markdown", "value": prefab_documentation(prefab) },
+                })
+            });
+        let symbols = self
+            .symbols(source)
+            .into_iter()
+            .unique_by(|symbol| symbol.name.clone())
+            .map(|symbol| {
+                json!({
+                    "label": symbol.name,
+                    "kind": match symbol.kind {
+                        SymbolKind::Definition => 3,
+                        SymbolKind::Label => 6,
+                    },
+                    "detail": symbol.detail,
+                })
+            });
+        Ok(Value::Array(symbols.chain(prefabs).collect()))
+    }
+
+    fn hover(&self, params: &Value) -> Result<Value> {
+        let (source, offset) = self.position(params)?;
+        let Some((name, span)) = name_at(source, offset) else {
+            return Ok(Value::Null);
+        };
+
+        let contents = match self.prefabs.get(name) {
+            Some(prefab) => prefab_documentation(prefab),
+            None => match self
+                .symbols(source)
+                .into_iter()
+                .find(|symbol| symbol.name == name)
+            {
+                Some(Symbol {
+                    kind: SymbolKind::Definition,
+                    detail,
+                    ..
+                }) => {
+                    format!("```\n{}\n```", detail.unwrap_or_default())
+                }
+                Some(Symbol {
+                    kind: SymbolKind::Label,
+                    detail,
+                    ..
+                }) => match detail {
+                    Some(kind) => format!("`{}`: `{}`", name, kind),
+                    None => format!("`{}`", name),
+                },
+                None => return Ok(Value::Null),
+            },
+        };
+        Ok(json!({
+            "contents": { "kind": "markdown", "value": contents },
+            "range": range(source, &span),
+        }))
+    }
+
+    fn definition(&self, params: &Value) -> Result<Value> {
+        let uri = string(&params["textDocument"]["uri"])?;
+        let (source, offset) = self.position(params)?;
+        let Some((name, _)) = name_at(source, offset) else {
+            return Ok(Value::Null);
+        };
+
+        let symbols = self.symbols(source);
+        let symbol = symbols
+            .iter()
+            .find(|symbol| symbol.name == name && symbol.kind == SymbolKind::Definition)
+            .or_else(|| symbols.iter().find(|symbol| symbol.name == name));
+        Ok(match symbol {
+            Some(symbol) => json!({ "uri": uri, "range": range(source, &symbol.name_span) }),
+            None => Value::Null,
+        })
+    }
+
+    fn document_symbols(&self, params: &Value) -> Result<Value> {
+        let uri = string(&params["textDocument"]["uri"])?;
+        let source = self.document(&uri)?;
+
+        let symbols = self
+            .symbols(source)
+            .into_iter()
+            .unique_by(|symbol| (symbol.name.clone(), symbol.kind))
+            .map(|symbol| {
+                json!({
+                    "name": symbol.name,
+                    "detail": symbol.detail,
+                    "kind": match symbol.kind {
+                        SymbolKind::Definition => 12,
+                        SymbolKind::Label => 13,
+                    },
+                    "range": range(source, &symbol.span),
+                    "selectionRange": range(source, &symbol.name_span),
+                })
+            })
+            .collect();
+        Ok(Value::Array(symbols))
+    }
+
+    /// Symbols of a document, which has none while it doesn't parse.
+    fn symbols(&self, source: &str) -> Vec<Symbol> {
+        match parser::parse_source(source) {
+            Ok(statements) => symbols(source, &statements, &self.prefabs),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn document(&self, uri: &str) -> Result<&str> {
+        self.documents
+            .get(uri)
+            .map(String::as_str)
+            .ok_or_else(|| anyhow!("Document isn't open: {}", uri))
+    }
+
+    /// Resolves the document and byte offset of a text document position.
+    fn position(&self, params: &Value) -> Result<(&str, usize)> {
+        let source = self.document(&string(&params["textDocument"]["uri"])?)?;
+        let position = &params["position"];
+        let line = position["line"].as_u64().unwrap_or_default() as usize;
+        let character = position["character"].as_u64().unwrap_or_default() as usize;
+        Ok((source, offset(source, line, character)))
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> Result<()> {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+    }
+
+    fn send(&mut self, message: Value) -> Result<()> {
+        let body = message.to_string();
+        write!(
+            self.writer,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Reads the next message, or nothing once the client closed the stream.
+fn read_message(reader: &mut impl BufRead) -> Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = Some(value.trim().parse::<usize>()?);
+        }
+    }
+
+    let length = length.ok_or_else(|| anyhow!("Message is missing its Content-Length!"))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+fn string(value: &Value) -> Result<String> {
+    value
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("Expected a string but got: {}", value))
+}
+
+/// Converts a line and UTF-16 character of the protocol into a byte offset.
+fn offset(source: &str, line: usize, character: usize) -> usize {
+    let start = source
+        .split_inclusive('\n')
+        .take(line)
+        .map(str::len)
+        .sum::<usize>();
+    let mut units = 0;
+    for (i, c) in source[start..].char_indices() {
+        if units >= character || c == '\n' {
+            return start + i;
+        }
+        units += c.len_utf16();
+    }
+    source.len()
+}
+
+/// Converts a byte offset into a line and UTF-16 character of the protocol.
+fn position(source: &str, offset: usize) -> Value {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count();
+    let start = before.rfind('\n').map_or(0, |i| i + 1);
+    let character = before[start..].encode_utf16().count();
+    json!({ "line": line, "character": character })
+}
+
+fn range(source: &str, span: &Span) -> Value {
+    json!({ "start": position(source, span.start), "end": position(source, span.end) })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::{read_message, Server};
+
+    /// Runs the server on the messages, returning the messages it sent back.
+    fn serve(messages: &[Value]) -> Vec<Value> {
+        let input = messages
+            .iter()
+            .map(|message| {
+                let body = message.to_string();
+                format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+            })
+            .collect::<String>();
+        let mut output = Vec::new();
+        Server::new(&mut output).run(&mut input.as_bytes()).unwrap();
+
+        let mut sent = Vec::new();
+        let mut reader = &output[..];
+        while let Some(message) = read_message(&mut reader).unwrap() {
+            sent.push(message);
+        }
+        sent
+    }
+
+    fn open(text: &str) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": "file:///main.ds", "text": text } },
+        })
+    }
+
+    fn hover(id: u64, uri: &str, line: u64, character: u64) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "textDocument/hover",
+            "params": {
+                "textDocument": { "uri": uri },
+                "position": { "line": line, "character": character },
+            },
+        })
+    }
+
+    #[test]
+    fn answers_initialize() {
+        let sent =
+            serve(&[json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} })]);
+        assert_eq!(sent[0]["id"], 1);
+        assert_eq!(sent[0]["result"]["capabilities"]["hoverProvider"], true);
+    }
+
+    #[test]
+    fn publishes_diagnostics_where_they_occur() {
+        let sent = serve(&[open("inspect_number(1)\ny = z")]);
+        assert_eq!(sent[0]["method"], "textDocument/publishDiagnostics");
+        let diagnostics = sent[0]["params"]["diagnostics"].as_array().unwrap();
+        let error = diagnostics
+            .iter()
+            .find(|diagnostic| diagnostic["severity"] == 1)
+            .unwrap();
+        assert_eq!(error["message"], "Can't find label with name: z");
+        assert_eq!(
+            error["range"],
+            json!({ "start": { "line": 1, "character": 0 }, "end": { "line": 1, "character": 5 } })
+        );
+    }
+
+    #[test]
+    fn hovers_prefabs() {
+        let sent = serve(&[open("win()"), hover(1, "file:///main.ds", 0, 1)]);
+        let contents = sent[1]["result"]["contents"]["value"].as_str().unwrap();
+        assert!(contents.contains("win"), "{}", contents);
+    }
+
+    #[test]
+    fn keeps_serving_after_requests_on_unopened_documents() {
+        let sent = serve(&[
+            hover(1, "file:///closed.ds", 0, 0),
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "shutdown" }),
+        ]);
+        assert_eq!(sent[0]["id"], 1);
+        assert_eq!(
+            sent[0]["error"]["message"],
+            "Document isn't open: file:///closed.ds"
+        );
+        assert_eq!(
+            sent[1],
+            json!({ "jsonrpc": "2.0", "id": 2, "result": null })
+        );
+    }
+}
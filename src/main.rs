@@ -1,9 +1,15 @@
 mod cli;
+mod formatter;
 mod game;
+mod interpreter;
 mod lexer;
+mod lsp;
 mod parser;
 mod transpiler;
 
 fn main() {
-    cli::run().unwrap();
+    if let Err(error) = cli::run() {
+        eprintln!("error: {:#}", error);
+        std::process::exit(1);
+    }
 }
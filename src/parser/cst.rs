@@ -0,0 +1,171 @@
+use winnow::Parser;
+
+use crate::{
+    lexer::{
+        self,
+        token::{Handedness, Kind, Token},
+    },
+    parser::{grammar::Statement, parse_source, SyntaxError},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(unused)]
+pub enum NodeKind {
+    Script,
+    Invocation,
+    Assignement,
+    While,
+    Definition,
+    /// The braces of a callback or definition with the statements between them.
+    Block,
+}
+
+#[derive(Debug, Clone)]
+pub enum Element<'s> {
+    Node(Node<'s>),
+    Token(Token<'s>),
+}
+
+/// A node of the concrete syntax tree. Whitespace and comments are kept as tokens between the
+/// others, so the tokens of a node add up to its exact source.
+#[derive(Debug, Clone)]
+#[allow(unused)]
+pub struct Node<'s> {
+    pub kind: NodeKind,
+    pub children: Vec<Element<'s>>,
+}
+
+impl<'s> Node<'s> {
+    /// All tokens of the node in source order.
+    pub fn tokens(&self) -> Vec<&Token<'s>> {
+        let mut tokens = Vec::new();
+        self.collect_tokens(&mut tokens);
+        tokens
+    }
+
+    fn collect_tokens<'a>(&'a self, tokens: &mut Vec<&'a Token<'s>>) {
+        for child in self.children.iter() {
+            match child {
+                Element::Node(node) => node.collect_tokens(tokens),
+                Element::Token(token) => tokens.push(token),
+            }
+        }
+    }
+
+    /// The source of the node, including its whitespace and comments.
+    pub fn text(&self) -> String {
+        self.tokens().iter().map(|token| token.value).collect()
+    }
+}
+
+/// A parsed script, both as the statements the transpiler works with and as a lossless tree.
+#[derive(Debug)]
+pub struct SyntaxTree<'s> {
+    pub root: Node<'s>,
+    pub statements: Vec<Statement>,
+}
+
+/// Parses a script into statements along with the tree of all its tokens.
+pub fn parse_tree(source: &str) -> Result<SyntaxTree<'_>, SyntaxError> {
+    let statements = parse_source(source)?;
+    let tokens = lexer::lossless_tokens
+        .parse(lexer::Source::new(source))
+        .map_err(|error| SyntaxError {
+            message: "Unexpected character".to_string(),
+            span: error.char_span(),
+        })?;
+
+    let root = build_node(NodeKind::Script, &tokens, &statements);
+    debug_assert_eq!(root.text(), source);
+    Ok(SyntaxTree { root, statements })
+}
+
+/// Groups the tokens covered by each statement into its node. Tokens between statements,
+/// including comments, stay with the parent.
+fn build_node<'s>(kind: NodeKind, tokens: &[Token<'s>], statements: &[Statement]) -> Node<'s> {
+    let mut children = Vec::new();
+    let mut i = 0;
+    for statement in statements {
+        let Some(span) = statement.span() else {
+            continue;
+        };
+        while i < tokens.len() && tokens[i].offset < span.start {
+            children.push(Element::Token(tokens[i]));
+            i += 1;
+        }
+        let end = tokens[i..]
+            .iter()
+            .position(|token| token.offset >= span.end)
+            .map_or(tokens.len(), |end| i + end);
+        children.push(Element::Node(build_statement(statement, &tokens[i..end])));
+        i = end;
+    }
+    children.extend(tokens[i..].iter().copied().map(Element::Token));
+
+    Node { kind, children }
+}
+
+fn build_statement<'s>(statement: &Statement, tokens: &[Token<'s>]) -> Node<'s> {
+    let (kind, mut blocks): (_, Vec<&[Statement]>) = match statement {
+        Statement::Invocation { callbacks, .. } => (
+            NodeKind::Invocation,
+            callbacks
+                .iter()
+                .map(|callback| &callback.statements[..])
+                .collect(),
+        ),
+        Statement::Assignement { .. } => (NodeKind::Assignement, Vec::new()),
+        Statement::While { statements, .. } => (NodeKind::While, vec![&statements[..]]),
+        Statement::Definition { statements, .. } => (NodeKind::Definition, vec![&statements[..]]),
+        Statement::Comment(_) => unreachable!(),
+    };
+    blocks.reverse();
+
+    // Blocks are the outermost pairs of braces, in the same order as the statement lists them.
+    // Only the `if` chained by `else if` stands in for its block without braces.
+    let mut children = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let chained = match blocks.last() {
+            Some([statement]) => statement.span().filter(|span| span.start == tokens[i].offset),
+            _ => None,
+        };
+        if let Some(span) = chained {
+            let end = tokens[i..]
+                .iter()
+                .position(|token| token.offset >= span.end)
+                .map_or(tokens.len(), |end| i + end);
+            let statements = blocks.pop().unwrap_or_default();
+            children.push(Element::Node(build_statement(&statements[0], &tokens[i..end])));
+            i = end;
+            continue;
+        }
+        if tokens[i].kind != Kind::Bracket(Handedness::Opening) {
+            children.push(Element::Token(tokens[i]));
+            i += 1;
+            continue;
+        }
+
+        let mut depth = 0;
+        let end = tokens[i..]
+            .iter()
+            .position(|token| {
+                match token.kind {
+                    Kind::Bracket(Handedness::Opening) => depth += 1,
+                    Kind::Bracket(Handedness::Closing) => depth -= 1,
+                    _ => {}
+                }
+                depth == 0
+            })
+            .map_or(tokens.len(), |end| i + end + 1);
+        let statements = blocks.pop().unwrap_or_default();
+        children.push(Element::Node(build_node(
+            NodeKind::Block,
+            &tokens[i..end],
+            statements,
+        )));
+        i = end;
+    }
+
+    Node { kind, children }
+}
@@ -1,18 +1,58 @@
-#[derive(Debug)]
+use std::ops::Range;
+
+/// Byte range of a node in the source.
+pub type Span = Range<usize>;
+
+#[derive(Debug, Clone, PartialEq)]
 #[allow(unused)]
 pub struct Input {
     pub label: Option<String>,
     pub value: Expression,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[allow(unused)]
 pub struct Output {
     pub label: Option<String>,
+    /// Set for global and saved variables, which are written instead of bound as a label.
+    pub modifier: Option<Modifier>,
     pub name: Option<String>,
+    /// Set when writing to an element of a list, like `$xs[i] = v`.
+    pub index: Option<Expression>,
+    /// Set when writing to a property of an object, like `obj.position = v`.
+    pub member: Option<String>,
+}
+
+impl Output {
+    /// Whether the output writes into an element or property of what its name holds, instead of
+    /// binding or setting the name itself.
+    pub fn is_indirect(&self) -> bool {
+        self.index.is_some() || self.member.is_some()
+    }
+
+    /// Reads what the output writes to, like `$xs[i]` for `$xs[i] = v`.
+    pub fn to_expression(&self) -> Option<Expression> {
+        let mut expression = Expression::Variable {
+            modifier: self.modifier.clone(),
+            name: self.name.clone()?,
+        };
+        if let Some(index) = &self.index {
+            expression = Expression::Index {
+                list: Box::new(expression),
+                index: Box::new(index.clone()),
+            };
+        }
+        if let Some(member) = &self.member {
+            expression = Expression::Member {
+                value: Box::new(expression),
+                member: member.clone(),
+            };
+        }
+        Some(expression)
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[allow(unused)]
 pub struct Callback {
     pub label: Option<String>,
@@ -20,7 +60,7 @@ pub struct Callback {
     pub statements: Vec<Statement>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[allow(unused)]
 pub enum Statement {
     Invocation {
@@ -28,10 +68,18 @@ pub enum Statement {
         inputs: Vec<Input>,
         outputs: Vec<Output>,
         callbacks: Vec<Callback>,
+        span: Span,
     },
     Assignement {
         value: Expression,
         outputs: Vec<Output>,
+        span: Span,
+    },
+    /// Repeats the statements within a frame as long as the condition holds.
+    While {
+        condition: Expression,
+        statements: Vec<Statement>,
+        span: Span,
     },
     Definition {
         name: String,
@@ -39,18 +87,61 @@ pub enum Statement {
         outputs: Vec<String>,
         callbacks: Vec<String>,
         statements: Vec<Statement>,
+        span: Span,
     },
     Comment(String),
 }
 
-#[derive(Debug)]
+impl Statement {
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            Statement::Invocation { span, .. }
+            | Statement::Assignement { span, .. }
+            | Statement::While { span, .. }
+            | Statement::Definition { span, .. } => Some(span),
+            Statement::Comment(_) => None,
+        }
+    }
+
+    pub(super) fn with_span(mut self, new: Span) -> Self {
+        match &mut self {
+            Statement::Invocation { span, .. }
+            | Statement::Assignement { span, .. }
+            | Statement::While { span, .. }
+            | Statement::Definition { span, .. } => *span = new,
+            Statement::Comment(_) => {}
+        }
+        self
+    }
+}
+
+/// Events handled with `on`, along with the sensor node each of them is sugar for.
+pub const EVENTS: [(&str, &str); 5] = [
+    ("play", "play_sensor"),
+    ("touch", "touch_sensor"),
+    ("swipe", "swipe_sensor"),
+    ("button", "button"),
+    ("collision", "collision"),
+];
+
+#[derive(Debug, Clone, PartialEq)]
 #[allow(unused)]
 pub enum Modifier {
     Global,
     Saved,
 }
 
-#[derive(Debug)]
+impl Modifier {
+    /// The prefix Fancade uses to tell global and saved variables apart from local ones.
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Modifier::Global => "$",
+            Modifier::Saved => "!",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 #[allow(unused)]
 pub enum Expression {
     Skip,
@@ -58,6 +149,8 @@ pub enum Expression {
     Integer(i32),
     Boolean(bool),
     String(String),
+    Vector([f64; 3]),
+    Rotation([f64; 3]),
     Call {
         name: String,
         inputs: Vec<Input>,
@@ -66,4 +159,37 @@ pub enum Expression {
         modifier: Option<Modifier>,
         name: String,
     },
+    /// The pointer to a variable rather than its value, written `&name`.
+    Reference(Box<Expression>),
+    /// Values written into consecutive elements of a list, like `[a, b, c]`.
+    List(Vec<Expression>),
+    /// A member of a value, like the components `v.x` or `v.xz` of a vector.
+    Member {
+        value: Box<Expression>,
+        member: String,
+    },
+    /// An element of a list, like `xs[i]`.
+    Index {
+        list: Box<Expression>,
+        index: Box<Expression>,
+    },
+    /// A call made on a value, like `obj.add_force(f)`, which passes the value as its first input.
+    Method {
+        value: Box<Expression>,
+        name: String,
+        inputs: Vec<Input>,
+    },
+}
+
+impl Expression {
+    /// The plain call a method call stands for, or the expression itself for anything else.
+    pub fn into_call(self) -> Expression {
+        match self {
+            Expression::Method { value, name, inputs } => Expression::Call {
+                name,
+                inputs: [Input { label: None, value: *value }].into_iter().chain(inputs).collect(),
+            },
+            expression => expression,
+        }
+    }
 }
@@ -1,16 +1,58 @@
-use crate::lexer::token::*;
+use crate::lexer::{self, token::*};
 use grammar::*;
 use winnow::{
     combinator::{
-        alt, delimited, opt, repeat, separated, separated_foldl1, separated_foldr1, seq, terminated,
+        alt, delimited, empty, opt, preceded, repeat, separated, separated_foldl1, separated_foldr1, seq, terminated,
     },
-    error::StrContext,
+    error::{ContextError, StrContext},
+    stream::TokenSlice,
     token::one_of,
     Parser, Result,
 };
 
+pub mod cst;
 pub mod grammar;
 
+/// A lexer or parser error at a location in the source.
+#[derive(Debug)]
+pub struct SyntaxError {
+    pub message: String,
+    pub span: Span,
+}
+
+/// Lexes and parses a whole script.
+pub fn parse_source(source: &str) -> std::result::Result<Vec<Statement>, SyntaxError> {
+    let tokens = lexer::tokens
+        .parse(lexer::Source::new(source))
+        .map_err(|error| SyntaxError {
+            message: match error.inner().to_string() {
+                message if message.is_empty() => "Unexpected character".to_string(),
+                message => message,
+            },
+            span: error.char_span(),
+        })?;
+
+    let statements = terminated(statements0, Kind::EndOfFile)
+        .parse(TokenSlice::new(&tokens))
+        .map_err(|error| {
+            let token = &tokens[error.offset().min(tokens.len() - 1)];
+            let message = match error.inner().to_string() {
+                message if !message.is_empty() => message,
+                _ if token.kind == Kind::EndOfFile => "Unexpected end of file".to_string(),
+                _ if matches!(token.kind, Kind::GlobalVariable | Kind::SavedVariable) => format!(
+                    "Unexpected `{}`, only variables can be global or saved, not calls, definitions or labels",
+                    token.value
+                ),
+                _ => format!("Unexpected `{}`", token.value),
+            };
+            SyntaxError {
+                message,
+                span: token.offset..token.offset + token.value.len(),
+            }
+        });
+    statements
+}
+
 pub fn name(i: &mut Tokens) -> Result<String> {
     Kind::Name.map(|&v| v.value.to_string()).parse_next(i)
 }
@@ -44,24 +86,32 @@ pub fn string(i: &mut Tokens) -> Result<String> {
         .parse_next(i)
 }
 
-pub fn modifier(i: &mut Tokens) -> Result<Modifier> {
-    Kind::Modifier
-        .map(|v| match v.value {
-            "$" => Modifier::Global,
-            "!" => Modifier::Saved,
-            _ => unreachable!(),
-        })
-        .parse_next(i)
+/// A global or saved variable, lexed together with its prefix.
+pub fn modified_name(i: &mut Tokens) -> Result<(Modifier, String)> {
+    alt((
+        Kind::GlobalVariable.map(|v| (Modifier::Global, v.value[1..].to_string())),
+        Kind::SavedVariable.map(|v| (Modifier::Saved, v.value[1..].to_string())),
+    ))
+    .parse_next(i)
 }
 
 pub fn variable(i: &mut Tokens) -> Result<Expression> {
-    seq! {Expression::Variable {
-        modifier: opt(modifier),
-        name: name,
-    }}
+    alt((
+        modified_name.map(|(modifier, name)| Expression::Variable {
+            modifier: Some(modifier),
+            name,
+        }),
+        name.map(|name| Expression::Variable { modifier: None, name }),
+    ))
     .parse_next(i)
 }
 
+pub fn reference(i: &mut Tokens) -> Result<Expression> {
+    preceded(Kind::Reference, variable)
+        .map(|variable| Expression::Reference(Box::new(variable)))
+        .parse_next(i)
+}
+
 pub fn input(i: &mut Tokens) -> Result<Input> {
     seq! {Input {
         label: opt(terminated(name, Kind::Label)),
@@ -94,9 +144,66 @@ pub fn grouping(i: &mut Tokens) -> Result<Expression> {
     .parse_next(i)
 }
 
+/// The three components of a vector or rotation in parentheses.
+pub fn components(i: &mut Tokens) -> Result<Vec<Input>> {
+    let component = || expression.map(|value| Input { label: None, value });
+    seq!(
+        _: Kind::Parenthesis(Handedness::Opening),
+        component(),
+        _: Kind::Comma,
+        component(),
+        _: Kind::Comma,
+        component(),
+        _: Kind::Parenthesis(Handedness::Closing),
+    )
+    .map(|(x, y, z)| vec![x, y, z])
+    .parse_next(i)
+}
+
+/// A name with a special meaning in some places, which stays usable as a name elsewhere.
+pub fn keyword<'i>(keyword: &'static str) -> impl Parser<Tokens<'i>, &'i Token<'i>, ContextError> {
+    Kind::Name.verify(move |token: &Token| token.value == keyword)
+}
+
+/// Vectors written as `vec(x, y, z)` or `(x, y, z)` and rotations written as `rot(x, y, z)`.
+/// They are sugar for `make_vector` and `make_rotation`, which get folded into constants.
+pub fn vector(i: &mut Tokens) -> Result<Expression> {
+    alt((
+        preceded(keyword("vec"), components).map(|inputs| ("make_vector", inputs)),
+        preceded(keyword("rot"), components).map(|inputs| ("make_rotation", inputs)),
+        components.map(|inputs| ("make_vector", inputs)),
+    ))
+    .map(|(name, inputs)| Expression::Call {
+        name: name.to_string(),
+        inputs,
+    })
+    .parse_next(i)
+}
+
+pub fn list(i: &mut Tokens) -> Result<Expression> {
+    delimited(
+        Kind::SquareBracket(Handedness::Opening),
+        separated(.., expression, Kind::Comma),
+        Kind::SquareBracket(Handedness::Closing),
+    )
+    .map(Expression::List)
+    .parse_next(i)
+}
+
+pub fn index(i: &mut Tokens) -> Result<Expression> {
+    delimited(
+        Kind::SquareBracket(Handedness::Opening),
+        expression,
+        Kind::SquareBracket(Handedness::Closing),
+    )
+    .parse_next(i)
+}
+
 pub fn simple_expression(i: &mut Tokens) -> Result<Expression> {
     alt((
+        vector,
         grouping,
+        list,
         Kind::Skip.map(|_| Expression::Skip),
         float.map(Expression::Float),
         integer.map(Expression::Integer),
@@ -104,10 +211,61 @@ pub fn simple_expression(i: &mut Tokens) -> Result<Expression> {
         string.map(Expression::String),
         call,
         variable,
+        reference,
     ))
     .parse_next(i)
 }
 
+/// What can follow a simple expression, binding tighter than any operator.
+enum Postfix {
+    Index(Expression),
+    Method(String, Vec<Input>),
+    Member(String),
+}
+
+pub fn postfix_expression(i: &mut Tokens) -> Result<Expression> {
+    (
+        simple_expression,
+        repeat(
+            ..,
+            alt((
+                index.map(Postfix::Index),
+                preceded(
+                    Kind::Dot,
+                    (
+                        name,
+                        delimited(
+                            Kind::Parenthesis(Handedness::Opening),
+                            inputs0,
+                            Kind::Parenthesis(Handedness::Closing),
+                        ),
+                    ),
+                )
+                .map(|(name, inputs)| Postfix::Method(name, inputs)),
+                preceded(Kind::Dot, name).map(Postfix::Member),
+            )),
+        ),
+    )
+        .map(|(value, postfixes): (_, Vec<_>)| {
+            postfixes.into_iter().fold(value, |value, postfix| match postfix {
+                Postfix::Index(index) => Expression::Index {
+                    list: Box::new(value),
+                    index: Box::new(index),
+                },
+                Postfix::Method(name, inputs) => Expression::Method {
+                    value: Box::new(value),
+                    name,
+                    inputs,
+                },
+                Postfix::Member(member) => Expression::Member {
+                    value: Box::new(value),
+                    member,
+                },
+            })
+        })
+        .parse_next(i)
+}
+
 pub fn prefix_expression(i: &mut Tokens) -> Result<Expression> {
     (
         repeat(
@@ -117,7 +275,7 @@ pub fn prefix_expression(i: &mut Tokens) -> Result<Expression> {
                 Kind::Operator(Operator::Subtract),
             ]),
         ),
-        simple_expression,
+        postfix_expression,
     )
         .map(|(ops, a): (Vec<&Token>, _)| {
             ops.iter().fold(a, |a, op| {
@@ -175,8 +333,8 @@ pub fn multiplicative_expression(i: &mut Tokens) -> Result<Expression> {
             };
             Expression::Call {
                 name: match op {
-                    Operator::Multiply => "add",
-                    Operator::Divide => "subtract",
+                    Operator::Multiply => "multiply",
+                    Operator::Divide => "divide",
                     _ => unreachable!(),
                 }
                 .to_string(),
@@ -338,14 +496,24 @@ pub fn expression(i: &mut Tokens) -> Result<Expression> {
 }
 
 pub fn output(i: &mut Tokens) -> Result<Output> {
-    seq! {Output {
-        label: opt(terminated(name, Kind::Label)),
-        name: alt((
-            name.map(Some),
-            Kind::Skip.value(None),
-        ))
-    }}
-    .parse_next(i)
+    let label = opt(terminated(name, Kind::Label)).parse_next(i)?;
+    let (modifier, name) = alt((
+        modified_name.map(|(modifier, name)| (Some(modifier), Some(name))),
+        name.map(|name| (None, Some(name))),
+        Kind::Skip.value((None, None)),
+    ))
+    .parse_next(i)?;
+    let (index, member) = match name {
+        Some(_) => (opt(index), opt(preceded(Kind::Dot, self::name))).parse_next(i)?,
+        None => (None, None),
+    };
+    Ok(Output {
+        label,
+        modifier,
+        name,
+        index,
+        member,
+    })
 }
 
 pub fn outputs0(i: &mut Tokens) -> Result<Vec<Output>> {
@@ -367,6 +535,15 @@ pub fn callback(i: &mut Tokens) -> Result<Callback> {
     .parse_next(i)
 }
 
+pub fn block(i: &mut Tokens) -> Result<Vec<Statement>> {
+    delimited(
+        Kind::Bracket(Handedness::Opening),
+        statements0,
+        Kind::Bracket(Handedness::Closing),
+    )
+    .parse_next(i)
+}
+
 pub fn callbacks0(i: &mut Tokens) -> Result<Vec<Callback>> {
     repeat(.., callback).parse_next(i)
 }
@@ -381,22 +558,190 @@ pub fn assignement(i: &mut Tokens) -> Result<Statement> {
     seq! {Statement::Assignement {
         outputs: opt(terminated(outputs1, Kind::Assignement)).map(Option::unwrap_or_default),
         value: expression,
+        span: empty.value(Span::default()),
     }}
     .parse_next(i)
 }
 
+/// Compound assignements like `x += e` are sugar for `x = x + e`.
+pub fn compound_assignement(i: &mut Tokens) -> Result<Statement> {
+    (
+        output.verify(|output: &Output| output.label.is_none() && output.name.is_some()),
+        one_of(|token: &Token| matches!(token.kind, Kind::CompoundAssignement(_))),
+        expression,
+    )
+        .map(|(output, operator, value): (Output, &Token, _)| {
+            let Kind::CompoundAssignement(operator) = operator.kind else {
+                unreachable!();
+            };
+            let Some(target) = output.to_expression() else {
+                unreachable!();
+            };
+            Statement::Assignement {
+                value: Expression::Call {
+                    name: match operator {
+                        Operator::Add => "add",
+                        Operator::Subtract => "subtract",
+                        Operator::Multiply => "multiply",
+                        Operator::Divide => "divide",
+                        _ => unreachable!(),
+                    }
+                    .to_string(),
+                    inputs: vec![
+                        Input {
+                            label: None,
+                            value: target,
+                        },
+                        Input { label: None, value },
+                    ],
+                },
+                outputs: vec![output],
+                span: Span::default(),
+            }
+        })
+        .parse_next(i)
+}
+
 pub fn invocation(i: &mut Tokens) -> Result<Statement> {
     seq! {Statement::Invocation {
         outputs: opt(terminated(outputs0, Kind::Assignement)).map(Option::unwrap_or_default),
-        name: name,
+        // Vector and rotation literals are values rather than nodes to invoke.
+        name: name.verify(|name: &str| !matches!(name, "vec" | "rot")),
         _: Kind::Parenthesis(Handedness::Opening),
         inputs: inputs0,
         _: Kind::Parenthesis(Handedness::Closing),
         callbacks: callbacks0,
+        span: empty.value(Span::default()),
+    }}
+    .parse_next(i)
+}
+
+/// `if c { } else { }` is sugar for the `true` and `false` callbacks of the `if` node, with
+/// `else if` nesting another one into the `false` callback.
+pub fn if_statement(i: &mut Tokens) -> Result<Statement> {
+    let (condition, then) = preceded(keyword("if"), (expression, block)).parse_next(i)?;
+    let otherwise = opt(preceded(
+        keyword("else"),
+        alt((
+            if_statement
+                .with_span()
+                .map(|(statement, span)| vec![statement.with_span(span)]),
+            block,
+        )),
+    ))
+    .parse_next(i)?;
+
+    let mut callbacks = vec![Callback {
+        label: Some("true".to_string()),
+        outputs: Vec::new(),
+        statements: then,
+    }];
+    if let Some(statements) = otherwise {
+        callbacks.push(Callback {
+            label: Some("false".to_string()),
+            outputs: Vec::new(),
+            statements,
+        });
+    }
+    Ok(Statement::Invocation {
+        name: "if".to_string(),
+        inputs: vec![Input {
+            label: None,
+            value: condition,
+        }],
+        outputs: Vec::new(),
+        callbacks,
+        span: Span::default(),
+    })
+}
+
+/// `for i in a..b { }` is sugar for the `do` callback of the `loop` node and its counter.
+pub fn for_statement(i: &mut Tokens) -> Result<Statement> {
+    let (counter, start, stop, statements) = preceded(
+        keyword("for"),
+        (
+            terminated(output, keyword("in")),
+            terminated(expression, Kind::Range),
+            expression,
+            block,
+        ),
+    )
+    .parse_next(i)?;
+
+    Ok(Statement::Invocation {
+        name: "loop".to_string(),
+        inputs: vec![
+            Input {
+                label: None,
+                value: start,
+            },
+            Input {
+                label: None,
+                value: stop,
+            },
+        ],
+        outputs: Vec::new(),
+        callbacks: vec![Callback {
+            label: Some("do".to_string()),
+            outputs: vec![counter],
+            statements,
+        }],
+        span: Span::default(),
+    })
+}
+
+pub fn while_statement(i: &mut Tokens) -> Result<Statement> {
+    seq! {Statement::While {
+        _: keyword("while"),
+        condition: expression,
+        statements: block,
+        span: empty.value(Span::default()),
     }}
     .parse_next(i)
 }
 
+/// `on touch |x, y| { }` is sugar for the callback of a sensor node, binding its outputs. A
+/// string after the event, like in `on button "Jump"`, is passed as its first option.
+pub fn event(i: &mut Tokens) -> Result<Statement> {
+    let (name, mut inputs, label, outputs, statements) = preceded(
+        keyword("on"),
+        (
+            self::name.verify_map(|event: String| {
+                EVENTS
+                    .iter()
+                    .find(|(name, _)| *name == event)
+                    .map(|(_, sensor)| sensor.to_string())
+            }),
+            opt(delimited(
+                Kind::Parenthesis(Handedness::Opening),
+                inputs0,
+                Kind::Parenthesis(Handedness::Closing),
+            ))
+            .map(Option::unwrap_or_default),
+            opt(string),
+            opt(delimited(Kind::Pipe, outputs0, Kind::Pipe)).map(Option::unwrap_or_default),
+            block,
+        ),
+    )
+    .parse_next(i)?;
+
+    inputs.extend(label.map(|label| Input {
+        label: None,
+        value: Expression::String(label),
+    }));
+    Ok(Statement::Invocation {
+        name,
+        inputs,
+        outputs: Vec::new(),
+        callbacks: vec![Callback {
+            label: None,
+            outputs,
+            statements,
+        }],
+        span: Span::default(),
+    })
+}
+
 pub fn definition(i: &mut Tokens) -> Result<Statement> {
     seq! {Statement::Definition {
         _: Kind::Definition,
@@ -409,12 +754,26 @@ pub fn definition(i: &mut Tokens) -> Result<Statement> {
         _: Kind::Bracket(Handedness::Opening),
         statements: statements0,
         _: Kind::Bracket(Handedness::Closing),
+        span: empty.value(Span::default()),
     }}
     .parse_next(i)
 }
 
 pub fn statement(i: &mut Tokens) -> Result<Statement> {
-    alt((comment, invocation, assignement, definition)).parse_next(i)
+    alt((
+        comment,
+        if_statement,
+        for_statement,
+        while_statement,
+        event,
+        invocation,
+        compound_assignement,
+        assignement,
+        definition,
+    ))
+        .with_span()
+        .map(|(statement, span)| statement.with_span(span))
+        .parse_next(i)
 }
 
 pub fn statements0(i: &mut Tokens) -> Result<Vec<Statement>> {
@@ -0,0 +1,125 @@
+use anyhow::{anyhow, Result};
+use std::fmt;
+
+use crate::game::{Game, Kind};
+
+/// Caps a game has to stay below to load in the app.
+///
+/// Fancade doesn't publish its limits, so the defaults are conservative estimates rather than
+/// documented values. Each of them can be overridden with the `--max-*` flags of `build`.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// Occupied cells rather than placed blocks, so a script block covering several cells counts
+    /// once for each of them.
+    pub blocks: usize,
+    pub wires: usize,
+    pub opts: usize,
+    pub size: [usize; 3],
+    pub chunks: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            blocks: 8192,
+            wires: 4096,
+            opts: 4096,
+            size: [256, 128, 256],
+            chunks: 1024,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Usage {
+    pub name: String,
+    pub used: usize,
+    pub limit: usize,
+}
+
+impl Usage {
+    pub fn is_exceeded(&self) -> bool {
+        self.used > self.limit
+    }
+}
+
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}/{}", self.name, self.used, self.limit)
+    }
+}
+
+/// Measures every level of the game as well as the custom chunks against the limits.
+pub fn measure_game(game: &Game, limits: &Limits) -> Vec<Usage> {
+    let mut usages = Vec::new();
+
+    for (i, chunk) in game.chunks.iter().enumerate() {
+        let Kind::Level = chunk.kind else {
+            continue;
+        };
+        let level = chunk.name.clone().unwrap_or_else(|| format!("#{}", i));
+
+        let (blocks, [z, y, x]) = match &chunk.blocks {
+            Some(blocks) => (blocks.iter().filter(|&&block| block != 0).count(), blocks.dim().into()),
+            None => (0, [0; 3]),
+        };
+        usages.push(Usage {
+            name: format!("{} block cells", level),
+            used: blocks,
+            limit: limits.blocks,
+        });
+        usages.push(Usage {
+            name: format!("{} wires", level),
+            used: chunk.wires.as_ref().map_or(0, Vec::len),
+            limit: limits.wires,
+        });
+        usages.push(Usage {
+            name: format!("{} opts", level),
+            used: chunk.opts.as_ref().map_or(0, Vec::len),
+            limit: limits.opts,
+        });
+        for (axis, used, limit) in [("x", x, limits.size[0]), ("y", y, limits.size[1]), ("z", z, limits.size[2])] {
+            usages.push(Usage {
+                name: format!("{} size {}", level, axis),
+                used,
+                limit,
+            });
+        }
+    }
+
+    usages.push(Usage {
+        name: "custom chunks".to_string(),
+        used: game
+            .chunks
+            .iter()
+            .filter(|chunk| !matches!(chunk.kind, Kind::Level))
+            .count(),
+        limit: limits.chunks,
+    });
+
+    usages
+}
+
+/// Warns about exceeded limits on stderr, failing if there are any and `deny` is set. The usage
+/// of every limit is only reported when `verbose` is set.
+pub fn check_budget(game: &Game, limits: &Limits, deny: bool, verbose: bool) -> Result<()> {
+    let usages = measure_game(game, limits);
+    if verbose {
+        for usage in usages.iter() {
+            eprintln!("{}", usage);
+        }
+    }
+
+    let exceeded: Vec<_> = usages.iter().filter(|usage| usage.is_exceeded()).collect();
+    if exceeded.is_empty() {
+        return Ok(());
+    }
+
+    for usage in exceeded.iter() {
+        eprintln!("warning: exceeded limit of {}", usage);
+    }
+    if deny {
+        return Err(anyhow!("Game exceeds {} limit(s)!", exceeded.len()));
+    }
+    Ok(())
+}
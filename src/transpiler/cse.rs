@@ -0,0 +1,88 @@
+use itertools::Itertools;
+use std::collections::HashMap;
+
+use crate::transpiler::{
+    graph::{Graph, NodeId},
+    prefabs::Prefab,
+};
+
+/// Merges nodes of pure prefabs with the same options and inputs, so that they are only placed
+/// once and their outputs are shared.
+pub fn eliminate_common_nodes(graph: &mut Graph, prefabs: &HashMap<String, Prefab>) {
+    let mut seen = HashMap::<String, NodeId>::new();
+    let mut replaced = HashMap::<NodeId, NodeId>::new();
+
+    // Inputs are lowered before the nodes consuming them, so they are already merged here.
+    for node in 0..graph.nodes.len() {
+        let prefab = &graph.nodes[node].prefab;
+        if !prefabs.get(prefab).is_some_and(Prefab::is_pure) {
+            continue;
+        }
+
+        let inputs = graph
+            .inputs(node)
+            .iter()
+            .map(|edge| {
+                let from = replaced.get(&edge.from.node).copied().unwrap_or(edge.from.node);
+                (edge.to.port, from, edge.from.port)
+            })
+            .collect_vec();
+        let key = format!("{} {:?} {:?}", prefab, graph.nodes[node].options, inputs);
+
+        match seen.get(&key) {
+            Some(&original) => {
+                replaced.insert(node, original);
+            }
+            None => {
+                seen.insert(key, node);
+            }
+        }
+    }
+
+    if replaced.is_empty() {
+        return;
+    }
+
+    for edge in graph.edges.iter_mut() {
+        if let Some(&original) = replaced.get(&edge.from.node) {
+            edge.from.node = original;
+        }
+    }
+    for (&node, &original) in replaced.iter() {
+        let comments = std::mem::take(&mut graph.nodes[node].comments);
+        graph.nodes[original].comments.extend(comments);
+    }
+    graph.remove_nodes(&replaced.keys().copied().collect());
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parser::parse_source,
+        transpiler::{build_graph, graph::Graph, prefabs::get_prefabs},
+    };
+
+    fn build(source: &str) -> Graph {
+        let statements = parse_source(source).unwrap();
+        build_graph(statements, &get_prefabs(), &mut Vec::new()).unwrap()
+    }
+
+    fn count(graph: &Graph, prefab: &str) -> usize {
+        graph.nodes.iter().filter(|node| node.prefab == prefab).count()
+    }
+
+    #[test]
+    fn merges_pure_nodes() {
+        let graph = build("inspect_number(min(2, current_frame()))\ninspect_number(min(2, current_frame()))");
+        assert_eq!(count(&graph, "min"), 1);
+        assert_eq!(count(&graph, "current_frame"), 1);
+        assert_eq!(count(&graph, "inspect_number"), 2);
+    }
+
+    #[test]
+    fn keeps_stateful_nodes_apart() {
+        let graph = build("motor_x(5)\nmotor_x(5)\ninspect_number(random())\ninspect_number(random())");
+        assert_eq!(count(&graph, "motor_x"), 2);
+        assert_eq!(count(&graph, "random"), 2);
+    }
+}
@@ -0,0 +1,379 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    parser::grammar::{Callback, Expression, Input, Output, Span, Statement},
+    transpiler::{infer::is_operator, prefabs::Prefab, Warning},
+};
+
+/// Names that are referenced somewhere in the script.
+#[derive(Debug, Default)]
+struct Usages {
+    calls: HashSet<String>,
+    variables: HashSet<String>,
+}
+
+/// Removes callbacks that can never fire, unused definitions and unused labels, warning about
+/// each of them. Statements after `win` or `lose` are warned about, but kept as they still run
+/// while the game ends.
+pub fn eliminate_dead_code(
+    statements: Vec<Statement>,
+    prefabs: &HashMap<String, Prefab>,
+    warnings: &mut Vec<Warning>,
+) -> Vec<Statement> {
+    let mut usages = Usages::default();
+    collect_statements(&statements, None, &mut usages);
+
+    eliminate_statements(statements, prefabs, &usages, warnings)
+}
+
+fn collect_statements(statements: &[Statement], definition: Option<&str>, usages: &mut Usages) {
+    for statement in statements {
+        match statement {
+            Statement::Invocation {
+                name,
+                inputs,
+                outputs,
+                callbacks,
+                ..
+            } => {
+                if Some(name.as_str()) != definition {
+                    usages.calls.insert(name.clone());
+                }
+                for input in inputs {
+                    collect_expression(&input.value, definition, usages);
+                }
+                collect_outputs(outputs, definition, usages);
+                for callback in callbacks {
+                    collect_outputs(&callback.outputs, definition, usages);
+                    collect_statements(&callback.statements, definition, usages);
+                }
+            }
+            Statement::Assignement { value, outputs, .. } => {
+                collect_expression(value, definition, usages);
+                collect_outputs(outputs, definition, usages);
+            }
+            Statement::While {
+                condition,
+                statements,
+                ..
+            } => {
+                collect_expression(condition, definition, usages);
+                collect_statements(statements, definition, usages);
+            }
+            Statement::Definition {
+                name,
+                outputs,
+                statements,
+                ..
+            } => {
+                usages.variables.extend(outputs.iter().cloned());
+                collect_statements(statements, Some(name), usages);
+            }
+            Statement::Comment(_) => {}
+        }
+    }
+}
+
+fn collect_expression(expression: &Expression, definition: Option<&str>, usages: &mut Usages) {
+    match expression {
+        Expression::Call { name, inputs } => {
+            if Some(name.as_str()) != definition {
+                usages.calls.insert(name.clone());
+            }
+            for input in inputs {
+                collect_expression(&input.value, definition, usages);
+            }
+        }
+        Expression::Variable { modifier: None, name } => {
+            usages.variables.insert(name.clone());
+        }
+        Expression::Reference(variable) => collect_expression(variable, definition, usages),
+        Expression::List(items) => {
+            for item in items {
+                collect_expression(item, definition, usages);
+            }
+        }
+        Expression::Member { value, .. } => collect_expression(value, definition, usages),
+        Expression::Index { list, index } => {
+            collect_expression(list, definition, usages);
+            collect_expression(index, definition, usages);
+        }
+        _ => {}
+    }
+}
+
+/// Writing to an element of a list or a property of an object reads the label holding it along
+/// with the index.
+fn collect_outputs(outputs: &[Output], definition: Option<&str>, usages: &mut Usages) {
+    for output in outputs.iter().filter(|output| output.is_indirect()) {
+        if let (None, Some(name)) = (&output.modifier, &output.name) {
+            usages.variables.insert(name.clone());
+        }
+        if let Some(index) = &output.index {
+            collect_expression(index, definition, usages);
+        }
+    }
+}
+
+fn eliminate_statements(
+    statements: Vec<Statement>,
+    prefabs: &HashMap<String, Prefab>,
+    usages: &Usages,
+    warnings: &mut Vec<Warning>,
+) -> Vec<Statement> {
+    let mut terminal = None::<String>;
+    let mut result = Vec::new();
+
+    for statement in statements {
+        if let Some(terminal) = &terminal {
+            if !matches!(statement, Statement::Comment(_)) {
+                warnings.push(Warning {
+                    message: format!("Statement after `{}` only runs until the game ends", terminal),
+                    span: statement.span().cloned(),
+                });
+            }
+        }
+
+        match statement {
+            Statement::Invocation {
+                name,
+                inputs,
+                outputs,
+                callbacks,
+                span,
+            } => {
+                warn_unused_outputs(&outputs, usages, &span, warnings);
+
+                let callbacks = match prefabs.get(&name) {
+                    Some(prefab) => {
+                        if prefab.terminal {
+                            terminal = Some(name.clone());
+                        }
+                        eliminate_callbacks(&name, prefab, &inputs, callbacks, &span, warnings)
+                    }
+                    None => callbacks,
+                };
+                // Labels bound by callbacks, like the position of a touch, aren't warned about.
+                let callbacks = callbacks
+                    .into_iter()
+                    .map(|callback| Callback {
+                        statements: eliminate_statements(callback.statements, prefabs, usages, warnings),
+                        ..callback
+                    })
+                    .collect();
+
+                result.push(Statement::Invocation {
+                    name,
+                    inputs,
+                    outputs,
+                    callbacks,
+                    span,
+                });
+            }
+            Statement::Assignement { value, outputs, span } => {
+                let is_unused = !outputs.is_empty()
+                    && outputs.iter().all(|output| {
+                        output.modifier.is_none()
+                            && !output.is_indirect()
+                            && output.name.as_ref().is_none_or(|name| !usages.variables.contains(name))
+                    });
+                warn_unused_outputs(&outputs, usages, &span, warnings);
+
+                // Lists can't be assigned to labels, which lowering reports instead.
+                if is_unused && is_pure(&value, prefabs) && !matches!(value, Expression::List(_)) {
+                    continue;
+                }
+                result.push(Statement::Assignement { value, outputs, span });
+            }
+            Statement::While {
+                condition,
+                statements,
+                span,
+            } => {
+                if let Expression::Boolean(false) = condition {
+                    warnings.push(Warning {
+                        message: "Body of `while` can never run".to_string(),
+                        span: Some(span),
+                    });
+                    continue;
+                }
+
+                result.push(Statement::While {
+                    condition,
+                    statements: eliminate_statements(statements, prefabs, usages, warnings),
+                    span,
+                });
+            }
+            Statement::Definition {
+                name,
+                inputs,
+                outputs,
+                callbacks,
+                statements,
+                span,
+            } => {
+                if !usages.calls.contains(&name) {
+                    warnings.push(Warning {
+                        message: format!("Unused definition `{}`", name),
+                        span: Some(span),
+                    });
+                    continue;
+                }
+
+                result.push(Statement::Definition {
+                    name,
+                    inputs,
+                    outputs,
+                    callbacks,
+                    statements: eliminate_statements(statements, prefabs, usages, warnings),
+                    span,
+                });
+            }
+            Statement::Comment(_) => result.push(statement),
+        }
+    }
+
+    result
+}
+
+/// Whether the expression only consists of pure prefabs and literals.
+pub fn is_pure(expression: &Expression, prefabs: &HashMap<String, Prefab>) -> bool {
+    match expression {
+        Expression::Skip
+        | Expression::Float(_)
+        | Expression::Integer(_)
+        | Expression::Boolean(_)
+        | Expression::String(_)
+        | Expression::Vector(_)
+        | Expression::Rotation(_) => true,
+        Expression::Call { name, inputs } => {
+            (is_operator(name) || prefabs.get(name).is_some_and(Prefab::is_pure))
+                && inputs.iter().all(|input| is_pure(&input.value, prefabs))
+        }
+        Expression::List(items) => items.iter().all(|item| is_pure(item, prefabs)),
+        Expression::Member { value, .. } => is_pure(value, prefabs),
+        Expression::Method { .. } => is_pure(&expression.clone().into_call(), prefabs),
+        Expression::Variable { .. } | Expression::Reference(_) | Expression::Index { .. } => false,
+    }
+}
+
+/// Drops callbacks that don't exist on the prefab or whose condition is known to never be met.
+fn eliminate_callbacks(
+    name: &str,
+    prefab: &Prefab,
+    inputs: &[Input],
+    callbacks: Vec<Callback>,
+    span: &Span,
+    warnings: &mut Vec<Warning>,
+) -> Vec<Callback> {
+    let condition = match (name, inputs) {
+        ("if", [input]) => match input.value {
+            Expression::Boolean(value) => Some(value),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    callbacks
+        .into_iter()
+        .enumerate()
+        .filter(|(i, callback)| {
+            let port = match &callback.label {
+                Some(label) => prefab.callbacks.iter().find(|port| &port.name == label),
+                None => prefab.callbacks.get(*i),
+            };
+            let Some(port) = port else {
+                warnings.push(Warning {
+                    message: match &callback.label {
+                        Some(label) => format!("`{}` has no callback `{}`", name, label),
+                        None => format!("`{}` has no callback at position {}", name, i),
+                    },
+                    span: Some(span.clone()),
+                });
+                return false;
+            };
+
+            let fires = match (condition, port.name.as_str()) {
+                (Some(condition), "true") => condition,
+                (Some(condition), "false") => !condition,
+                _ => true,
+            };
+            if !fires {
+                warnings.push(Warning {
+                    message: format!("Callback `{}` of `{}` can never fire", port.name, name),
+                    span: Some(span.clone()),
+                });
+            }
+            fires
+        })
+        .map(|(_, callback)| callback)
+        .collect()
+}
+
+fn warn_unused_outputs(
+    outputs: &[Output],
+    usages: &Usages,
+    span: &Span,
+    warnings: &mut Vec<Warning>,
+) {
+    // Variables are read by other scripts or later sessions, so only labels can be unused.
+    let labels = outputs
+        .iter()
+        .filter(|output| output.modifier.is_none() && !output.is_indirect());
+    for name in labels.filter_map(|output| output.name.as_ref()) {
+        if !usages.variables.contains(name) {
+            warnings.push(Warning {
+                message: format!("Unused label `{}`", name),
+                span: Some(span.clone()),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::eliminate_dead_code;
+    use crate::{
+        parser::{grammar::Statement, parse_source},
+        transpiler::{fold::fold_statements, prefabs::get_prefabs, Warning},
+    };
+
+    fn eliminate(source: &str) -> (Vec<Statement>, Vec<String>) {
+        let mut warnings = Vec::<Warning>::new();
+        let statements = fold_statements(parse_source(source).unwrap());
+        let statements = eliminate_dead_code(statements, &get_prefabs(), &mut warnings);
+        (statements, warnings.into_iter().map(|warning| warning.message).collect())
+    }
+
+    #[test]
+    fn ignores_labels_bound_by_callbacks() {
+        let (_, warnings) = eliminate("on touch |x, y| {\n  inspect_number(x)\n}\nfor i in 0..3 {}");
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn warns_about_statements_after_win() {
+        let (statements, warnings) = eliminate("win(delay: 20)\n# still runs\n$score = 1");
+        assert_eq!(statements.len(), 3);
+        assert_eq!(warnings, ["Statement after `win` only runs until the game ends"]);
+    }
+
+    #[test]
+    fn drops_unused_arithmetic() {
+        let (statements, warnings) = eliminate("sum = 1 + 2\n$score = 3");
+        assert_eq!(statements.len(), 1);
+        assert_eq!(warnings, ["Unused label `sum`"]);
+    }
+
+    #[test]
+    fn keeps_lists_assigned_to_labels() {
+        let (statements, _) = eliminate("xs = [1, 2]");
+        assert_eq!(statements.len(), 1);
+    }
+
+    #[test]
+    fn drops_callbacks_that_never_fire() {
+        let (_, warnings) = eliminate("if False {\n  $score = 1\n}");
+        assert_eq!(warnings, ["Callback `true` of `if` can never fire"]);
+    }
+}
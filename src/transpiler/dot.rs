@@ -0,0 +1,169 @@
+use anyhow::Result;
+use std::{collections::HashMap, io::Write};
+
+use crate::{
+    game::{Game, Kind, OptData, WireKind},
+    transpiler::{
+        graph::Graph,
+        prefabs::{get_prefabs, Prefab},
+        raise::raise_level,
+    },
+};
+
+/// Writes the scripts of all levels in the game as a Graphviz digraph, with one cluster per level.
+pub fn write_dot(writer: &mut impl Write, game: &Game) -> Result<()> {
+    let prefabs = get_prefabs();
+
+    writeln!(writer, "digraph {{")?;
+    writeln!(writer, "  node [shape=box, fontname=monospace];")?;
+
+    let levels = game
+        .chunks
+        .iter()
+        .filter(|chunk| matches!(chunk.kind, Kind::Level))
+        .enumerate();
+    for (level, chunk) in levels {
+        let graph = raise_level(chunk, &prefabs)?;
+        let name = chunk.name.clone().unwrap_or_else(|| format!("Level {}", level));
+
+        writeln!(writer, "  subgraph cluster_{} {{", level)?;
+        writeln!(writer, "    label={};", quote(&name))?;
+        write_graph(writer, &graph, &prefabs, &format!("l{}_", level))?;
+        writeln!(writer, "  }}")?;
+    }
+
+    writeln!(writer, "}}")?;
+    Ok(())
+}
+
+fn write_graph(
+    writer: &mut impl Write,
+    graph: &Graph,
+    prefabs: &HashMap<String, Prefab>,
+    prefix: &str,
+) -> Result<()> {
+    for (id, node) in graph.nodes.iter().enumerate() {
+        let prefab = prefabs.get(&node.prefab);
+        let mut label = node.prefab.clone();
+        for (index, data) in node.options.iter() {
+            let name = prefab
+                .and_then(|prefab| prefab.options.get(*index as usize))
+                .map_or_else(|| index.to_string(), |opt| opt.name.clone());
+            label.push_str(&format!("\n{}: {}", name, format_option(data)));
+        }
+        writeln!(writer, "    {}{} [label={}];", prefix, id, quote(&label))?;
+    }
+
+    for edge in graph.edges.iter() {
+        let from = prefabs.get(&graph.nodes[edge.from.node].prefab);
+        let to = prefabs.get(&graph.nodes[edge.to.node].prefab);
+        let attributes = match edge.kind {
+            WireKind::Execute => {
+                let label = match edge.from.port {
+                    0 => String::new(),
+                    port => from
+                        .and_then(|prefab| prefab.callbacks.get(port - 1))
+                        .map_or_else(|| port.to_string(), |port| port.name.clone()),
+                };
+                format!("label={}, color=goldenrod, penwidth=2", quote(&label))
+            }
+            WireKind::Value(_) => {
+                let output = from
+                    .and_then(|prefab| prefab.outputs.get(edge.from.port))
+                    .map_or_else(|| edge.from.port.to_string(), |port| port.name.clone());
+                let input = to
+                    .and_then(|prefab| prefab.inputs.get(edge.to.port))
+                    .map_or_else(|| edge.to.port.to_string(), |port| port.name.clone());
+                format!(
+                    "taillabel={}, headlabel={}, style=dashed, color=steelblue",
+                    quote(&output),
+                    quote(&input)
+                )
+            }
+        };
+        writeln!(
+            writer,
+            "    {}{} -> {}{} [{}];",
+            prefix, edge.from.node, prefix, edge.to.node, attributes
+        )?;
+    }
+    Ok(())
+}
+
+fn format_option(data: &OptData) -> String {
+    match data {
+        OptData::Int8(value) => value.to_string(),
+        OptData::Int16(value) => value.to_string(),
+        OptData::Float32(value) => value.to_string(),
+        OptData::Vec([x, y, z]) => format!("({}, {}, {})", x, y, z),
+        OptData::Name(value)
+        | OptData::Execute(value)
+        | OptData::Input(value)
+        | OptData::This(value)
+        | OptData::Pointer(value)
+        | OptData::Object(value)
+        | OptData::Output(value)
+        | OptData::Unknown(_, value) => format!("{:?}", value),
+    }
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::write_dot;
+    use crate::{
+        game::{Game, Kind},
+        parser::parse_source,
+        transpiler::{build_graph, graph::Graph, prefabs::get_prefabs, raise::raise_level, transpile_game},
+    };
+
+    const SOURCE: &str = "$x = 1
+if $x > 0 {
+  inspect_number($x)
+} else {
+  $x = vec(1, 2, 3).y
+}
+";
+
+    fn dot(game: &Game) -> String {
+        let mut output = Vec::new();
+        write_dot(&mut output, game).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    /// The prefabs and ports a wire connects.
+    type Wire<'a> = (&'a str, usize, &'a str, usize);
+
+    /// The prefabs of the nodes and the wires between them, regardless of order.
+    fn shape(graph: &Graph) -> (Vec<&str>, Vec<Wire<'_>>) {
+        let prefab = |node: usize| graph.nodes[node].prefab.as_str();
+        let nodes = graph.nodes.iter().map(|node| node.prefab.as_str()).sorted().collect();
+        let edges = graph
+            .edges
+            .iter()
+            .map(|edge| (prefab(edge.from.node), edge.from.port, prefab(edge.to.node), edge.to.port))
+            .sorted()
+            .collect();
+        (nodes, edges)
+    }
+
+    #[test]
+    fn loads_built_games_into_the_same_graph() {
+        let game = transpile_game(parse_source(SOURCE).unwrap(), &mut Vec::new()).unwrap();
+        let mut bytes = Vec::new();
+        game.write(&mut bytes).unwrap();
+        let loaded = Game::read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(dot(&loaded), dot(&game));
+
+        let prefabs = get_prefabs();
+        let built = build_graph(parse_source(SOURCE).unwrap(), &prefabs, &mut Vec::new()).unwrap();
+        let level = loaded.chunks.iter().find(|chunk| matches!(chunk.kind, Kind::Level)).unwrap();
+        let raised = raise_level(level, &prefabs).unwrap();
+        assert_eq!(shape(&raised), shape(&built));
+    }
+}
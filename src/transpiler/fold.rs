@@ -0,0 +1,293 @@
+use itertools::Itertools;
+
+use crate::{
+    game::RawKind,
+    parser::grammar::{Callback, Expression, Input, Statement},
+    transpiler::infer::{is_operator, resolve_operator, swizzle},
+};
+
+/// A value known at transpile time.
+#[derive(Debug, Clone, Copy)]
+pub enum Constant {
+    Number(f64),
+    Vector([f64; 3]),
+    Rotation([f64; 3]),
+    Truth(bool),
+}
+
+impl Constant {
+    pub fn from_expression(expression: &Expression) -> Option<Self> {
+        match expression {
+            &Expression::Float(value) => Some(Constant::Number(value)),
+            &Expression::Integer(value) => Some(Constant::Number(value as f64)),
+            &Expression::Boolean(value) => Some(Constant::Truth(value)),
+            &Expression::Vector(value) => Some(Constant::Vector(value)),
+            &Expression::Rotation(value) => Some(Constant::Rotation(value)),
+            Expression::Call { name, inputs } => match (name.as_str(), &inputs[..]) {
+                ("true", []) => Some(Constant::Truth(true)),
+                ("false", []) => Some(Constant::Truth(false)),
+                ("number" | "vector" | "rotation", [input]) if input.label.is_none() => {
+                    Constant::from_expression(&input.value)
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn kind(&self) -> RawKind {
+        match self {
+            Constant::Number(_) => RawKind::Number,
+            Constant::Vector(_) => RawKind::Vector,
+            Constant::Rotation(_) => RawKind::Rotation,
+            Constant::Truth(_) => RawKind::Truth,
+        }
+    }
+
+    pub fn into_expression(self) -> Expression {
+        match self {
+            Constant::Number(value) => Expression::Float(value),
+            Constant::Vector(value) => Expression::Vector(value),
+            Constant::Rotation(value) => Expression::Rotation(value),
+            Constant::Truth(value) => Expression::Boolean(value),
+        }
+    }
+}
+
+/// Evaluates a pure prefab with constant inputs, if the result is known at transpile time.
+pub fn evaluate(name: &str, inputs: &[Constant]) -> Option<Constant> {
+    use Constant::*;
+
+    let result = match (name, inputs) {
+        ("negate", &[Number(a)]) => Number(-a),
+        ("add_numbers", &[Number(a), Number(b)]) => Number(a + b),
+        ("subtract_numbers", &[Number(a), Number(b)]) => Number(a - b),
+        ("multiply", &[Number(a), Number(b)]) => Number(a * b),
+        ("divide", &[Number(a), Number(b)]) if b != 0.0 => Number(a / b),
+        ("power", &[Number(a), Number(b)]) => Number(a.powf(b)),
+        ("logarithm", &[Number(a), Number(b)]) if a > 0.0 && b > 0.0 && b != 1.0 => Number(a.log(b)),
+        ("sin", &[Number(a)]) => Number(a.to_radians().sin()),
+        ("cos", &[Number(a)]) => Number(a.to_radians().cos()),
+        ("absolute", &[Number(a)]) => Number(a.abs()),
+        ("floor", &[Number(a)]) => Number(a.floor()),
+        ("ceiling", &[Number(a)]) => Number(a.ceil()),
+        ("min", &[Number(a), Number(b)]) => Number(a.min(b)),
+        ("max", &[Number(a), Number(b)]) => Number(a.max(b)),
+        ("make_vector", &[Number(x), Number(y), Number(z)]) => Vector([x, y, z]),
+        ("make_rotation", &[Number(x), Number(y), Number(z)]) => Rotation([x, y, z]),
+        ("add_vectors", &[Vector(a), Vector(b)]) => Vector([a[0] + b[0], a[1] + b[1], a[2] + b[2]]),
+        ("subtract_vectors", &[Vector(a), Vector(b)]) => {
+            Vector([a[0] - b[0], a[1] - b[1], a[2] - b[2]])
+        }
+        ("scale", &[Vector(a), Number(b)]) => Vector([a[0] * b, a[1] * b, a[2] * b]),
+        ("dot_product", &[Vector(a), Vector(b)]) => Number(a[0] * b[0] + a[1] * b[1] + a[2] * b[2]),
+        ("cross_product", &[Vector(a), Vector(b)]) => Vector([
+            a[1] * b[2] - a[2] * b[1],
+            a[2] * b[0] - a[0] * b[2],
+            a[0] * b[1] - a[1] * b[0],
+        ]),
+        ("distance", &[Vector(a), Vector(b)]) => {
+            Number(((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt())
+        }
+        ("normalize", &[Vector(a)]) => {
+            let length = (a[0] * a[0] + a[1] * a[1] + a[2] * a[2]).sqrt();
+            if length == 0.0 {
+                return None;
+            }
+            Vector([a[0] / length, a[1] / length, a[2] / length])
+        }
+        ("less_than", &[Number(a), Number(b)]) => Truth(a < b),
+        ("greater_than", &[Number(a), Number(b)]) => Truth(a > b),
+        ("equal_numbers", &[Number(a), Number(b)]) => Truth(a == b),
+        ("equal_vectors", &[Vector(a), Vector(b)]) => Truth(a == b),
+        ("equal_truths", &[Truth(a), Truth(b)]) => Truth(a == b),
+        ("not", &[Truth(a)]) => Truth(!a),
+        ("and", &[Truth(a), Truth(b)]) => Truth(a && b),
+        ("or", &[Truth(a), Truth(b)]) => Truth(a || b),
+        _ => return None,
+    };
+
+    match result {
+        Number(value) if !value.is_finite() => None,
+        result => Some(result),
+    }
+}
+
+pub fn fold_statements(statements: Vec<Statement>) -> Vec<Statement> {
+    statements.into_iter().map(fold_statement).collect()
+}
+
+pub fn fold_statement(statement: Statement) -> Statement {
+    match statement {
+        Statement::Invocation {
+            name,
+            inputs,
+            outputs,
+            callbacks,
+            span,
+        } => Statement::Invocation {
+            name,
+            inputs: fold_inputs(inputs),
+            outputs,
+            callbacks: callbacks
+                .into_iter()
+                .map(|callback| Callback {
+                    statements: fold_statements(callback.statements),
+                    ..callback
+                })
+                .collect(),
+            span,
+        },
+        Statement::Assignement { value, outputs, span } => Statement::Assignement {
+            value: fold_expression(value),
+            outputs,
+            span,
+        },
+        Statement::While {
+            condition,
+            statements,
+            span,
+        } => Statement::While {
+            condition: fold_expression(condition),
+            statements: fold_statements(statements),
+            span,
+        },
+        Statement::Definition {
+            name,
+            inputs,
+            outputs,
+            callbacks,
+            statements,
+            span,
+        } => Statement::Definition {
+            name,
+            inputs,
+            outputs,
+            callbacks,
+            statements: fold_statements(statements),
+            span,
+        },
+        Statement::Comment(value) => Statement::Comment(value),
+    }
+}
+
+/// Replaces every pure subtree with only constant inputs by its value.
+pub fn fold_expression(expression: Expression) -> Expression {
+    let (name, inputs) = match expression {
+        Expression::Call { name, inputs } => (name, inputs),
+        // Method calls are only kept apart for the formatter, the passes after folding see calls.
+        method @ Expression::Method { .. } => return fold_expression(method.into_call()),
+        Expression::List(items) => return Expression::List(items.into_iter().map(fold_expression).collect()),
+        Expression::Member { value, member } => {
+            let value = fold_expression(*value);
+            return match (Constant::from_expression(&value), swizzle(&member).as_deref()) {
+                (Some(Constant::Vector(value) | Constant::Rotation(value)), Some(&[component])) => {
+                    Expression::Float(value[component])
+                }
+                _ => Expression::Member {
+                    value: Box::new(value),
+                    member,
+                },
+            };
+        }
+        Expression::Index { list, index } => {
+            return Expression::Index {
+                list: Box::new(fold_expression(*list)),
+                index: Box::new(fold_expression(*index)),
+            }
+        }
+        expression => return expression,
+    };
+    let inputs = fold_inputs(inputs);
+
+    let constants = inputs
+        .iter()
+        .map(|input| match input.label {
+            None => Constant::from_expression(&input.value),
+            Some(_) => None,
+        })
+        .collect::<Option<Vec<_>>>();
+
+    match constants.and_then(|constants| evaluate_call(&name, &constants)) {
+        Some(constant) => constant.into_expression(),
+        None => Expression::Call { name, inputs },
+    }
+}
+
+/// Evaluates a call with constant inputs, placing operators as the prefab they are lowered to.
+fn evaluate_call(name: &str, inputs: &[Constant]) -> Option<Constant> {
+    if !is_operator(name) {
+        return evaluate(name, inputs);
+    }
+    let &[a, b] = inputs else {
+        return None;
+    };
+    let operation = resolve_operator(name, a.kind(), b.kind())?;
+    let operands = match operation.swapped {
+        false => [a, b],
+        true => [b, a],
+    };
+    let result = evaluate(operation.prefab, &operands)?;
+    match operation.negated {
+        false => Some(result),
+        true => evaluate("not", &[result]),
+    }
+}
+
+fn fold_inputs(inputs: Vec<Input>) -> Vec<Input> {
+    inputs
+        .into_iter()
+        .map(|input| Input {
+            value: fold_expression(input.value),
+            ..input
+        })
+        .collect_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fold_statements;
+    use crate::parser::{
+        grammar::{Expression, Statement},
+        parse_source,
+    };
+
+    /// Folds the expression assigned to a label.
+    fn fold(expression: &str) -> Expression {
+        let statements = fold_statements(parse_source(&format!("x = {}", expression)).unwrap());
+        let [Statement::Assignement { value, .. }] = &statements[..] else {
+            panic!("expected an assignement, found {:?}", statements);
+        };
+        value.clone()
+    }
+
+    #[test]
+    fn folds_arithmetic() {
+        assert_eq!(fold("1 + 2 * 3"), Expression::Float(7.0));
+        assert_eq!(fold("-(2 ** 3)"), Expression::Float(-8.0));
+        assert_eq!(fold("8 / max(1, 4)"), Expression::Float(2.0));
+    }
+
+    #[test]
+    fn folds_vectors() {
+        assert_eq!(fold("vec(1, 2, 3) + vec(1, 1, 1)"), Expression::Vector([2.0, 3.0, 4.0]));
+        assert_eq!(fold("2 * vec(1, 2, 3)"), Expression::Vector([2.0, 4.0, 6.0]));
+        assert_eq!(fold("vec(1, 2, 3).y"), Expression::Float(2.0));
+    }
+
+    #[test]
+    fn folds_comparisons() {
+        assert_eq!(fold("2 <= 3"), Expression::Boolean(true));
+        assert_eq!(fold("2 >= 3"), Expression::Boolean(false));
+        assert_eq!(fold("1 != 1"), Expression::Boolean(false));
+        assert_eq!(fold("vec(1, 2, 3) == vec(1, 2, 3)"), Expression::Boolean(true));
+        assert_eq!(fold("True and not False"), Expression::Boolean(true));
+    }
+
+    #[test]
+    fn keeps_what_is_not_constant() {
+        assert!(matches!(fold("1 + random()"), Expression::Call { name, .. } if name == "add"));
+        assert!(matches!(fold("1 / 0"), Expression::Call { name, .. } if name == "divide"));
+        assert!(matches!(fold("vec(1, 2, 3) * vec(1, 2, 3)"), Expression::Call { name, .. } if name == "multiply"));
+    }
+}
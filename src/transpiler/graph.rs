@@ -0,0 +1,136 @@
+use std::collections::HashSet;
+
+use itertools::Itertools;
+
+use crate::{
+    game::{OptData, WireKind},
+    parser::grammar::Span,
+};
+
+pub type NodeId = usize;
+
+/// An instance of a prefab, independent of where it ends up being placed.
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub prefab: String,
+    pub options: Vec<(u8, OptData)>,
+    /// Comments written in front of the statement that created the node.
+    pub comments: Vec<String>,
+    /// Source of the statement that created the node.
+    pub span: Option<Span>,
+}
+
+impl Node {
+    pub fn new(prefab: &str) -> Self {
+        Self {
+            prefab: prefab.to_string(),
+            options: Vec::new(),
+            comments: Vec::new(),
+            span: None,
+        }
+    }
+}
+
+/// A port of a node. Value ports index into the inputs or outputs of the prefab, execute ports
+/// are `0` for the regular flow and `1..` for the callbacks of the prefab.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Endpoint {
+    pub node: NodeId,
+    pub port: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Edge {
+    pub kind: WireKind,
+    pub from: Endpoint,
+    pub to: Endpoint,
+}
+
+/// Script as a graph of prefab instances connected by value and execute wires.
+#[derive(Debug, Default, Clone)]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+impl Graph {
+    pub fn add_node(&mut self, node: Node) -> NodeId {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    pub fn connect(&mut self, kind: WireKind, from: Endpoint, to: Endpoint) {
+        self.edges.push(Edge { kind, from, to });
+    }
+
+    /// Value edges going into the node, ordered by input.
+    pub fn inputs(&self, node: NodeId) -> Vec<&Edge> {
+        self.edges
+            .iter()
+            .filter(|edge| edge.to.node == node && edge.kind != WireKind::Execute)
+            .sorted_by_key(|edge| edge.to.port)
+            .collect()
+    }
+
+    /// Value edges coming out of the node.
+    pub fn outputs(&self, node: NodeId) -> Vec<&Edge> {
+        self.edges
+            .iter()
+            .filter(|edge| edge.from.node == node && edge.kind != WireKind::Execute)
+            .collect()
+    }
+
+    /// Execute edges coming out of the node, ordered by port.
+    pub fn successors(&self, node: NodeId) -> Vec<&Edge> {
+        self.edges
+            .iter()
+            .filter(|edge| edge.from.node == node && edge.kind == WireKind::Execute)
+            .sorted_by_key(|edge| edge.from.port)
+            .collect()
+    }
+
+    /// Execute edges going into the node.
+    pub fn predecessors(&self, node: NodeId) -> Vec<&Edge> {
+        self.edges
+            .iter()
+            .filter(|edge| edge.to.node == node && edge.kind == WireKind::Execute)
+            .collect()
+    }
+
+    /// Removes the nodes along with their edges, renumbering the remaining nodes.
+    pub fn remove_nodes(&mut self, removed: &HashSet<NodeId>) {
+        let mut ids = Vec::with_capacity(self.nodes.len());
+        let mut next = 0;
+        for id in 0..self.nodes.len() {
+            ids.push(next);
+            if !removed.contains(&id) {
+                next += 1;
+            }
+        }
+
+        let nodes = std::mem::take(&mut self.nodes);
+        self.nodes = nodes
+            .into_iter()
+            .enumerate()
+            .filter(|(id, _)| !removed.contains(id))
+            .map(|(_, node)| node)
+            .collect();
+
+        let edges = std::mem::take(&mut self.edges);
+        self.edges = edges
+            .into_iter()
+            .filter(|edge| !removed.contains(&edge.from.node) && !removed.contains(&edge.to.node))
+            .map(|edge| Edge {
+                from: Endpoint {
+                    node: ids[edge.from.node],
+                    ..edge.from
+                },
+                to: Endpoint {
+                    node: ids[edge.to.node],
+                    ..edge.to
+                },
+                ..edge
+            })
+            .collect();
+    }
+}
@@ -0,0 +1,346 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+use crate::{
+    game::{RawKind, ValueKind},
+    parser::grammar::{Expression, Input, Modifier, Output, Statement},
+    transpiler::{prefabs::Prefab, TranspileError},
+};
+
+/// The name Fancade knows a global or saved variable by.
+pub fn variable_name(modifier: &Modifier, name: &str) -> String {
+    format!("{}{}", modifier.prefix(), name)
+}
+
+/// How prefabs dealing with a type of value are suffixed, like `get_number`.
+pub fn kind_name(kind: RawKind) -> &'static str {
+    match kind {
+        RawKind::Number => "number",
+        RawKind::Vector => "vector",
+        RawKind::Rotation => "rotation",
+        RawKind::Truth => "truth",
+        RawKind::Object => "object",
+        RawKind::Constraint => "constraint",
+    }
+}
+
+/// A property of objects along with the prefabs reading and writing it.
+pub struct Property {
+    pub getter: &'static str,
+    pub setter: &'static str,
+    pub kind: RawKind,
+}
+
+/// Looks up a property of objects, like `obj.position`. Properties are named like the ports of
+/// their prefabs.
+pub fn property(name: &str) -> Option<Property> {
+    let (getter, setter, kind) = match name {
+        "position" => ("get_position", "set_position", RawKind::Vector),
+        "rotation" => ("get_position", "set_position", RawKind::Rotation),
+        "velocity" => ("get_velocity", "set_velocity", RawKind::Vector),
+        "spin" => ("get_velocity", "set_velocity", RawKind::Vector),
+        _ => return None,
+    };
+    Some(Property { getter, setter, kind })
+}
+
+/// The components a member of a vector or rotation selects, like `[0, 2]` for `xz`. Components
+/// have to be named in order, as the ones left out are zero.
+pub fn swizzle(member: &str) -> Option<Vec<usize>> {
+    let components = member
+        .chars()
+        .map(|component| "xyz".find(component))
+        .collect::<Option<Vec<_>>>()?;
+    let ordered = components.windows(2).all(|pair| pair[0] < pair[1]);
+    (!components.is_empty() && ordered).then_some(components)
+}
+
+/// Whether the call was parsed from an operator without a prefab of its own, which is resolved
+/// on the types of its operands.
+pub fn is_operator(name: &str) -> bool {
+    matches!(
+        name,
+        "add" | "subtract" | "multiply" | "divide" | "equal" | "not_equal" | "at_most" | "at_least"
+    )
+}
+
+/// The symbol an operator is written with, for messages.
+pub fn operator_symbol(name: &str) -> &'static str {
+    match name {
+        "add" => "+",
+        "subtract" => "-",
+        "multiply" => "*",
+        "divide" => "/",
+        "equal" => "==",
+        "not_equal" => "!=",
+        "at_most" => "<=",
+        "at_least" => ">=",
+        _ => "?",
+    }
+}
+
+/// How an operator is placed for the types of its operands.
+pub struct Operation {
+    pub prefab: &'static str,
+    /// Whether the operands are passed to the prefab the other way around.
+    pub swapped: bool,
+    /// Whether the result of the prefab is passed through `not`, like for `!=`.
+    pub negated: bool,
+}
+
+/// The prefab an operator is placed as, depending on the types of its operands.
+pub fn resolve_operator(name: &str, a: RawKind, b: RawKind) -> Option<Operation> {
+    use RawKind::*;
+
+    let (prefab, swapped, negated) = match (name, a, b) {
+        ("add", Number, Number) => ("add_numbers", false, false),
+        ("add", Vector, Vector) => ("add_vectors", false, false),
+        ("subtract", Number, Number) => ("subtract_numbers", false, false),
+        ("subtract", Vector, Vector) => ("subtract_vectors", false, false),
+        ("multiply", Number, Number) => ("multiply", false, false),
+        ("multiply", Vector, Number) => ("scale", false, false),
+        ("multiply", Number, Vector) => ("scale", true, false),
+        ("divide", Number, Number) => ("divide", false, false),
+        ("equal" | "not_equal", Number, Number) => ("equal_numbers", false, name == "not_equal"),
+        ("equal" | "not_equal", Vector, Vector) => ("equal_vectors", false, name == "not_equal"),
+        ("equal" | "not_equal", Truth, Truth) => ("equal_truths", false, name == "not_equal"),
+        ("equal" | "not_equal", Object, Object) => ("equal_objects", false, name == "not_equal"),
+        ("at_most", Number, Number) => ("greater_than", false, true),
+        ("at_least", Number, Number) => ("less_than", false, true),
+        _ => return None,
+    };
+    Some(Operation {
+        prefab,
+        swapped,
+        negated,
+    })
+}
+
+pub fn raw_kind(kind: ValueKind) -> RawKind {
+    match kind {
+        ValueKind::Raw(kind) | ValueKind::Reference(kind) => kind,
+    }
+}
+
+/// Infers the type of every global and saved variable from the values assigned to it, keyed by
+/// the variable's name in Fancade.
+pub fn infer_variables(
+    statements: &[Statement],
+    prefabs: &HashMap<String, Prefab>,
+) -> Result<HashMap<String, RawKind>> {
+    let mut inference = Inference {
+        prefabs,
+        labels: HashMap::new(),
+        variables: HashMap::new(),
+    };
+
+    // Variables can be assigned from each other in any order, so repeat until nothing changes.
+    loop {
+        let known = inference.labels.len() + inference.variables.len();
+        inference.statements(statements)?;
+        if inference.labels.len() + inference.variables.len() == known {
+            return Ok(inference.variables);
+        }
+    }
+}
+
+struct Inference<'a> {
+    prefabs: &'a HashMap<String, Prefab>,
+    labels: HashMap<String, RawKind>,
+    variables: HashMap<String, RawKind>,
+}
+
+impl Inference<'_> {
+    fn statements(&mut self, statements: &[Statement]) -> Result<()> {
+        for statement in statements {
+            self.statement(statement)
+                .map_err(|error| TranspileError::locate(error, statement.span()))?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<()> {
+        match statement {
+            Statement::Invocation {
+                name,
+                outputs,
+                callbacks,
+                inputs,
+                ..
+            } => {
+                self.inputs(name, inputs)?;
+                self.bind_outputs(name, outputs)?;
+                for callback in callbacks {
+                    self.bind_outputs(name, &callback.outputs)?;
+                    self.statements(&callback.statements)?;
+                }
+            }
+            Statement::Assignement { value, outputs, .. } => {
+                self.expression_inputs(value)?;
+                match (value, &outputs[..]) {
+                    (Expression::Call { name, .. }, [_, _, ..]) => self.bind_outputs(name, outputs)?,
+                    (value, [output]) => {
+                        let kind = self
+                            .expression(value)
+                            .or_else(|| self.accumulation(value, &output.to_expression()?));
+                        if let Some(kind) = kind {
+                            self.bind(output, kind)?;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Statement::While {
+                condition,
+                statements,
+                ..
+            } => {
+                self.expression_inputs(condition)?;
+                self.statements(statements)?;
+            }
+            Statement::Definition { statements, .. } => self.statements(statements)?,
+            Statement::Comment(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Infers variables passed by reference from the type of the ports they are passed to.
+    fn inputs(&mut self, prefab: &str, inputs: &[Input]) -> Result<()> {
+        let ports = self
+            .prefabs
+            .get(prefab)
+            .map(|prefab| &prefab.inputs[..])
+            .unwrap_or_default();
+        for (i, input) in inputs.iter().enumerate() {
+            match (&input.value, ports.get(i)) {
+                (Expression::Reference(variable), Some(port)) => {
+                    if let Expression::Variable {
+                        modifier: Some(modifier),
+                        name,
+                    } = &**variable
+                    {
+                        self.bind_variable(variable_name(modifier, name), raw_kind(port.kind))?;
+                    }
+                }
+                (value, _) => self.expression_inputs(value)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn expression_inputs(&mut self, expression: &Expression) -> Result<()> {
+        match expression {
+            Expression::Call { name, inputs } => self.inputs(name, inputs),
+            Expression::Method { .. } => self.expression_inputs(&expression.clone().into_call()),
+            Expression::List(items) => items.iter().try_for_each(|item| self.expression_inputs(item)),
+            Expression::Member { value, .. } => self.expression_inputs(value),
+            Expression::Index { list, index } => {
+                self.expression_inputs(list)?;
+                self.expression_inputs(index)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn bind_outputs(&mut self, prefab: &str, outputs: &[Output]) -> Result<()> {
+        let Some(prefab) = self.prefabs.get(prefab) else {
+            return Ok(());
+        };
+        for (output, port) in outputs.iter().zip(prefab.outputs.iter()) {
+            self.bind(output, raw_kind(port.kind))?;
+        }
+        Ok(())
+    }
+
+    fn bind(&mut self, output: &Output, kind: RawKind) -> Result<()> {
+        let Some(name) = &output.name else {
+            return Ok(());
+        };
+        if let Some(index) = &output.index {
+            self.expression_inputs(index)?;
+        }
+        // Writing to a property of an object leaves the type of what holds the object as is.
+        if output.member.is_some() {
+            return Ok(());
+        }
+        let Some(modifier) = &output.modifier else {
+            // Writing to an element keeps the type of the label holding the list.
+            if output.index.is_none() {
+                self.labels.insert(name.clone(), kind);
+            }
+            return Ok(());
+        };
+
+        self.bind_variable(variable_name(modifier, name), kind)
+    }
+
+    fn bind_variable(&mut self, name: String, kind: RawKind) -> Result<()> {
+        match self.variables.insert(name.clone(), kind) {
+            Some(previous) if previous != kind => Err(anyhow!(
+                "Variable `{}` is used both as a {:?} and as a {:?}!",
+                name,
+                previous,
+                kind
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Variables accumulating into themselves, like `$score += 1`, can't be inferred from both
+    /// operands, so the target takes the type of the operand it is combined with.
+    fn accumulation(&self, value: &Expression, target: &Expression) -> Option<RawKind> {
+        let Expression::Call { name, inputs } = value else {
+            return self.expression(value);
+        };
+        let ([a, b], true) = (&inputs[..], is_operator(name)) else {
+            return self.expression(value);
+        };
+        let (a, b) = match (a.value == *target, b.value == *target) {
+            (true, true) => return None,
+            (true, false) => {
+                let kind = self.accumulation(&b.value, target)?;
+                (kind, kind)
+            }
+            (false, true) => {
+                let kind = self.accumulation(&a.value, target)?;
+                (kind, kind)
+            }
+            (false, false) => (self.accumulation(&a.value, target)?, self.accumulation(&b.value, target)?),
+        };
+        let operation = resolve_operator(name, a, b)?;
+        Some(raw_kind(self.prefabs.get(operation.prefab)?.outputs.first()?.kind))
+    }
+
+    fn expression(&self, expression: &Expression) -> Option<RawKind> {
+        match expression {
+            Expression::Float(_) | Expression::Integer(_) => Some(RawKind::Number),
+            Expression::Boolean(_) => Some(RawKind::Truth),
+            Expression::Vector(_) => Some(RawKind::Vector),
+            Expression::Rotation(_) => Some(RawKind::Rotation),
+            Expression::Call { name, inputs } if is_operator(name) => {
+                let [a, b] = &inputs[..] else {
+                    return None;
+                };
+                let operation = resolve_operator(name, self.expression(&a.value)?, self.expression(&b.value)?)?;
+                Some(raw_kind(self.prefabs.get(operation.prefab)?.outputs.first()?.kind))
+            }
+            Expression::Call { name, .. } => Some(raw_kind(self.prefabs.get(name)?.outputs.first()?.kind)),
+            Expression::Variable { modifier: None, name } => self.labels.get(name).copied(),
+            Expression::Variable {
+                modifier: Some(modifier),
+                name,
+            } => self.variables.get(&variable_name(modifier, name)).copied(),
+            Expression::List(items) => items.iter().find_map(|item| self.expression(item)),
+            Expression::Member { value, member } => match self.expression(value)? {
+                RawKind::Object => Some(property(member)?.kind),
+                kind => match swizzle(member)?.len() {
+                    1 => Some(RawKind::Number),
+                    _ => Some(kind),
+                },
+            },
+            Expression::Index { list, .. } | Expression::Reference(list) => self.expression(list),
+            Expression::Method { .. } => self.expression(&expression.clone().into_call()),
+            Expression::Skip | Expression::String(_) => None,
+        }
+    }
+}
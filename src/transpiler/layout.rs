@@ -0,0 +1,223 @@
+use anyhow::{anyhow, Result};
+use std::{cmp::min, collections::HashMap};
+
+use crate::{
+    game::OptData,
+    transpiler::{
+        graph::{Graph, NodeId},
+        prefabs::Prefab,
+        Block, Context, Opt, Port, Wire,
+    },
+};
+
+/// Positions the nodes of the graph, placing execution flows top to bottom and their inputs to
+/// their left.
+pub fn layout_graph(graph: &Graph, prefabs: &HashMap<String, Prefab>) -> Result<Context> {
+    let mut layout = Layout {
+        graph,
+        prefabs,
+        ctx: Context::default(),
+        placed: HashMap::new(),
+    };
+
+    for node in 0..graph.nodes.len() {
+        let is_head = graph.predecessors(node).is_empty()
+            && (graph.outputs(node).is_empty() || !graph.successors(node).is_empty());
+        if is_head && !layout.placed.contains_key(&node) {
+            layout.layout_flow(node)?;
+        }
+    }
+
+    Ok(layout.ctx)
+}
+
+struct Layout<'a> {
+    graph: &'a Graph,
+    prefabs: &'a HashMap<String, Prefab>,
+    ctx: Context,
+    placed: HashMap<NodeId, Block>,
+}
+
+impl<'a> Layout<'a> {
+    /// Places the node and every node executed after it, returning the block of the node.
+    fn layout_flow(&mut self, node: NodeId) -> Result<Block> {
+        let head = self.place_statement(node)?;
+        let mut prev = (node, head.clone());
+
+        loop {
+            let (node, ref block) = prev;
+            let mut next = None;
+
+            for edge in self.graph.successors(node) {
+                if edge.from.port == 0 {
+                    next = Some(edge.to.node);
+                    continue;
+                }
+
+                let x = self.ctx.pos[0];
+                self.ctx.pos[0] += block.parts.dim().2 as i32 + 1;
+                let callback = self.layout_flow(edge.to.node)?;
+                self.ctx.pos[0] = x;
+
+                self.ctx.wires.push(Wire {
+                    from: Port {
+                        position: block.position,
+                        offset: output_offset(block, edge.from.port - 1),
+                    },
+                    to: Port {
+                        position: callback.position,
+                        offset: before_offset(&callback),
+                    },
+                });
+            }
+
+            let Some(next) = next else {
+                break;
+            };
+            let new = self.place_statement(next)?;
+            self.ctx.wires.push(Wire {
+                from: Port {
+                    position: block.position,
+                    offset: [0o03, 0o01, 0o00],
+                },
+                to: Port {
+                    position: new.position,
+                    offset: before_offset(&new),
+                },
+            });
+            prev = (next, new);
+        }
+
+        Ok(head)
+    }
+
+    fn place_statement(&mut self, node: NodeId) -> Result<Block> {
+        self.place_comments(node)?;
+
+        let prefab = self.prefab(node)?;
+        let height = prefab.parts.dim().0 as i32;
+        let mut pos = self.ctx.pos;
+        self.ctx.pos[0] -= 1;
+        pos[2] -= height;
+
+        let block = Block {
+            parts: prefab.parts.clone(),
+            position: pos,
+        };
+        self.place(node, &block);
+
+        self.place_inputs(node, &block)?;
+
+        self.ctx.pos[0] += 1;
+        self.ctx.pos[2] = min(self.ctx.pos[2], pos[2]);
+        Ok(block)
+    }
+
+    fn place_value(&mut self, node: NodeId) -> Result<Block> {
+        if let Some(block) = self.placed.get(&node) {
+            return Ok(block.clone());
+        }
+
+        let prefab = self.prefab(node)?;
+        let height = prefab.parts.dim().0 as i32;
+        let width = prefab.parts.dim().2 as i32;
+        self.ctx.pos[0] -= width;
+        let mut pos = self.ctx.pos;
+
+        self.ctx.pos[0] -= 1;
+        pos[2] -= height;
+
+        let block = Block {
+            parts: prefab.parts.clone(),
+            position: pos,
+        };
+        self.place(node, &block);
+
+        self.place_inputs(node, &block)?;
+
+        self.ctx.pos[0] += width + 1;
+        self.ctx.pos[2] = min(self.ctx.pos[2], pos[2]);
+        Ok(block)
+    }
+
+    fn place_inputs(&mut self, node: NodeId, block: &Block) -> Result<()> {
+        for edge in self.graph.inputs(node) {
+            let prev = self.place_value(edge.from.node)?;
+            let callbacks = self.prefab(edge.from.node)?.callbacks.len();
+            self.ctx.wires.push(Wire {
+                from: Port {
+                    position: prev.position,
+                    offset: output_offset(&prev, callbacks + edge.from.port),
+                },
+                to: Port {
+                    position: block.position,
+                    offset: [0o00, 0o01, 0o03 + (block.parts.dim().0 as u16 - edge.to.port as u16 - 1) * 8],
+                },
+            });
+        }
+        Ok(())
+    }
+
+    fn place_comments(&mut self, node: NodeId) -> Result<()> {
+        let comments = &self.graph.nodes[node].comments;
+        if comments.is_empty() {
+            return Ok(());
+        }
+        let prefab = self.prefabs.get("comment").ok_or_else(|| anyhow!("Can't find prefab with name: comment"))?;
+
+        for comment in comments {
+            self.ctx.pos[2] -= 1;
+
+            for line in textwrap::wrap(comment.as_str(), 16) {
+                let height = prefab.parts.dim().0 as i32;
+                self.ctx.pos[2] -= height;
+                self.ctx.pos[0] += 1;
+                self.ctx.blocks.push(Block {
+                    parts: prefab.parts.clone(),
+                    position: self.ctx.pos,
+                });
+                self.ctx.opts.push(Opt {
+                    data: OptData::Name(line.to_string()),
+                    position: self.ctx.pos,
+                    index: 0,
+                });
+                self.ctx.pos[0] -= 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn place(&mut self, node: NodeId, block: &Block) {
+        self.ctx.blocks.push(block.clone());
+        for (index, data) in self.graph.nodes[node].options.iter() {
+            self.ctx.opts.push(Opt {
+                index: *index,
+                position: block.position,
+                data: data.clone(),
+            });
+        }
+        self.placed.insert(node, block.clone());
+    }
+
+    fn prefab(&self, node: NodeId) -> Result<&'a Prefab> {
+        let name = &self.graph.nodes[node].prefab;
+        self.prefabs
+            .get(name)
+            .ok_or_else(|| anyhow!("Can't find prefab with name: {}", name))
+    }
+}
+
+/// Offset of the execute input on top of the block.
+fn before_offset(block: &Block) -> [u16; 3] {
+    [0o03, 0o01, 0o06 + (block.parts.dim().0 as u16 - 1) * 8]
+}
+
+/// Offset of the port in the given row on the right side of the block, where callbacks come
+/// before value outputs.
+fn output_offset(block: &Block, row: usize) -> [u16; 3] {
+    [
+        0x06 + (block.parts.dim().2 as u16 - 1) * 8,
+        0o01,
+        0o03 + (block.parts.dim().0 as u16 - row as u16 - 1) * 8,
+    ]
+}
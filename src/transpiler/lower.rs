@@ -0,0 +1,1165 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+use crate::{
+    formatter::format_expression,
+    game::{OptData, OptKind, RawKind, ValueKind, WireKind},
+    parser::grammar::{Callback, Expression, Input, Output, Span, Statement},
+    transpiler::{
+        graph::{Endpoint, Graph, Node, NodeId},
+        infer::{
+            infer_variables, is_operator, kind_name, operator_symbol, property, raw_kind, resolve_operator, swizzle, variable_name,
+        },
+        prefabs::{self, Prefab},
+        TranspileError,
+    },
+};
+
+/// Lowers parsed statements into a graph of prefab instances.
+pub fn lower_program(statements: Vec<Statement>, prefabs: &HashMap<String, Prefab>) -> Result<Graph> {
+    let mut lowering = Lowering {
+        graph: Graph::default(),
+        prefabs,
+        labels: HashMap::new(),
+        variables: infer_variables(&statements, prefabs)?,
+        comments: Vec::new(),
+        loops: 0,
+        definitions: HashMap::new(),
+        inlined: Vec::new(),
+    };
+
+    lowering.lower_statements(statements)?;
+
+    for comment in std::mem::take(&mut lowering.comments) {
+        for line in textwrap::wrap(comment.as_str(), 16) {
+            let mut node = Node::new("comment");
+            node.options.push((0, OptData::Name(line.to_string())));
+            lowering.graph.add_node(node);
+        }
+    }
+
+    Ok(lowering.graph)
+}
+
+struct Lowering<'a> {
+    graph: Graph,
+    prefabs: &'a HashMap<String, Prefab>,
+    labels: HashMap<String, Endpoint>,
+    /// Types of the global and saved variables, keyed by their name in Fancade.
+    variables: HashMap<String, RawKind>,
+    /// Comments waiting for the next statement to be attached to.
+    comments: Vec<String>,
+    /// Number of `while` loops lowered so far, which each need a variable of their own.
+    loops: usize,
+    /// Definitions by name, which are inlined wherever they are called.
+    definitions: HashMap<String, Definition>,
+    /// Definitions being inlined, the innermost last.
+    inlined: Vec<Inlining>,
+}
+
+#[derive(Debug, Clone)]
+struct Definition {
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+    callbacks: Vec<String>,
+    statements: Vec<Statement>,
+}
+
+/// A call of a definition being inlined, along with what the caller passed to it.
+struct Inlining {
+    name: String,
+    /// Statements of the callbacks passed by the caller, by the name the definition uses.
+    callbacks: HashMap<String, Vec<Statement>>,
+    /// Labels of the caller, which its callbacks are lowered with.
+    labels: HashMap<String, Endpoint>,
+}
+
+/// How often both loops a `while` loop is lowered to repeat, so it repeats within a frame at
+/// most the square of this.
+const WHILE_LOOP: i32 = 32;
+
+impl Lowering<'_> {
+    /// Lowers a block of statements, returning the first node that gets executed.
+    fn lower_statements(&mut self, statements: Vec<Statement>) -> Result<Option<NodeId>> {
+        let flow = self.lower_block(statements)?;
+        Ok(self.chain(&flow))
+    }
+
+    /// Lowers a block of statements, returning the nodes placed into the flow in execution order.
+    fn lower_block(&mut self, statements: Vec<Statement>) -> Result<Vec<NodeId>> {
+        // Definitions can be called before the statement defining them.
+        for statement in statements.iter() {
+            if let Statement::Definition {
+                name,
+                inputs,
+                outputs,
+                callbacks,
+                statements,
+                ..
+            } = statement
+            {
+                let definition = Definition {
+                    inputs: inputs.clone(),
+                    outputs: outputs.clone(),
+                    callbacks: callbacks.clone(),
+                    statements: statements.clone(),
+                };
+                self.definitions.insert(name.clone(), definition);
+            }
+        }
+
+        let mut flow = Vec::new();
+        for statement in statements {
+            let comments = std::mem::take(&mut self.comments);
+            let span = statement.span().cloned();
+            let nodes = self
+                .lower_statement(statement)
+                .map_err(|error| TranspileError::locate(error, span.as_ref()))?;
+            // Value nodes aren't placed in the flow, so their comments carry over.
+            match nodes.first() {
+                Some(&first) => self.graph.nodes[first].comments = comments,
+                None => {
+                    self.comments.splice(0..0, comments);
+                }
+            }
+            flow.extend(nodes);
+        }
+        Ok(flow)
+    }
+
+    /// Connects the nodes to execute one after another, returning the first one.
+    fn chain(&mut self, nodes: &[NodeId]) -> Option<NodeId> {
+        for pair in nodes.windows(2) {
+            self.graph.connect(
+                WireKind::Execute,
+                Endpoint { node: pair[0], port: 0 },
+                Endpoint { node: pair[1], port: 0 },
+            );
+        }
+        nodes.first().copied()
+    }
+
+    fn lower_statement(&mut self, statement: Statement) -> Result<Vec<NodeId>> {
+        match statement {
+            Statement::Invocation { name, inputs, .. } if self.is_passed_callback(&name) => {
+                if !inputs.is_empty() {
+                    return Err(anyhow!("Callback `{}` can't be passed inputs!", name));
+                }
+                self.lower_passed_callback(&name)
+            }
+            Statement::Invocation {
+                name,
+                inputs,
+                outputs,
+                callbacks,
+                span,
+            } if self.definitions.contains_key(&name) => {
+                let (mut flow, results) = self.lower_inlined(&name, inputs, callbacks)?;
+                for (result, output) in results.into_iter().zip(outputs.iter()) {
+                    flow.extend(self.bind_output(result, output, &span)?);
+                }
+                Ok(flow)
+            }
+            Statement::Assignement {
+                value: Expression::Call { name, inputs },
+                outputs,
+                span,
+            } if self.definitions.contains_key(&name) => {
+                let (mut flow, results) = self.lower_inlined(&name, inputs, Vec::new())?;
+                for (result, output) in results.into_iter().zip(outputs.iter()) {
+                    flow.extend(self.bind_output(result, output, &span)?);
+                }
+                Ok(flow)
+            }
+            Statement::Invocation {
+                name,
+                inputs,
+                outputs,
+                callbacks,
+                span,
+            } => {
+                let node = self.lower_call(&name, inputs)?;
+                self.graph.nodes[node].span = Some(span.clone());
+                let mut flow = Vec::new();
+                if self.prefab(node)?.callable {
+                    flow.push(node);
+                }
+                flow.extend(self.bind_outputs(node, &outputs, &span)?);
+                self.lower_callbacks(node, callbacks, &span)?;
+                Ok(flow)
+            }
+            Statement::Assignement {
+                value: Expression::List(items),
+                outputs,
+                span,
+            } => self.lower_list(items, &outputs, &span),
+            Statement::Assignement { value, outputs, span } => {
+                if let Some(node) = self.lower_step(&value, &outputs, &span)? {
+                    return Ok(vec![node]);
+                }
+                let endpoint = self.lower_expression(value)?;
+                // Method calls like `obj.add_force(f)` are the only values that get executed.
+                let mut flow = Vec::new();
+                if let Some(endpoint) = endpoint {
+                    if self.prefab(endpoint.node)?.callable {
+                        self.graph.nodes[endpoint.node].span = Some(span.clone());
+                        flow.push(endpoint.node);
+                    }
+                }
+                match (endpoint, &outputs[..]) {
+                    (_, []) => {}
+                    (Some(endpoint), [output]) => flow.extend(self.bind_output(endpoint, output, &span)?),
+                    (Some(endpoint), outputs) => flow.extend(self.bind_outputs(endpoint.node, outputs, &span)?),
+                    (None, _) => return Err(anyhow!("Can't assign a skipped value!")),
+                }
+                Ok(flow)
+            }
+            Statement::While {
+                condition,
+                statements,
+                span,
+            } => self.lower_while(condition, statements, span),
+            // Definitions are collected with the block they are in.
+            Statement::Definition { .. } => Ok(Vec::new()),
+            Statement::Comment(value) => {
+                self.comments.push(value);
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    /// Lowers a `while` loop to an outer `loop` node repeating an inner one, which checks the
+    /// condition on every iteration. As loops can't be stopped early, a local variable holds how
+    /// often the inner loop repeats and drops to zero once the condition first fails, so the rest
+    /// of the outer loop executes nothing.
+    fn lower_while(&mut self, condition: Expression, statements: Vec<Statement>, span: Span) -> Result<Vec<NodeId>> {
+        // Names can't contain spaces, so no variable of the script shares this one.
+        let remaining = Expression::String(format!("while {}", self.loops));
+        self.loops += 1;
+
+        let inputs = |values: Vec<Expression>| {
+            values
+                .into_iter()
+                .map(|value| Input { label: None, value })
+                .collect::<Vec<_>>()
+        };
+        let call = |name: &str, values: Vec<Expression>| Expression::Call {
+            name: name.to_string(),
+            inputs: inputs(values),
+        };
+        let invoke = |name: &str, values: Vec<Expression>, callbacks: Vec<(&str, Vec<Statement>)>| {
+            Statement::Invocation {
+                name: name.to_string(),
+                inputs: inputs(values),
+                outputs: Vec::new(),
+                callbacks: callbacks
+                    .into_iter()
+                    .map(|(label, statements)| Callback {
+                        label: Some(label.to_string()),
+                        outputs: Vec::new(),
+                        statements,
+                    })
+                    .collect(),
+                span: span.clone(),
+            }
+        };
+        let set_remaining = |count: i32| invoke("set_number", vec![Expression::Integer(count), remaining.clone()], Vec::new());
+        let get_remaining = || call("get_number", vec![remaining.clone()]);
+
+        let running = call("greater_than", vec![get_remaining(), Expression::Integer(0)]);
+        let check = invoke(
+            "if",
+            vec![call("and", vec![running, condition])],
+            vec![("true", statements), ("false", vec![set_remaining(0)])],
+        );
+        let inner = invoke("loop", vec![Expression::Integer(0), get_remaining()], vec![("do", vec![check])]);
+        let outer = invoke(
+            "loop",
+            vec![Expression::Integer(0), Expression::Integer(WHILE_LOOP)],
+            vec![("do", vec![inner])],
+        );
+
+        self.lower_block(vec![set_remaining(WHILE_LOOP), outer])
+    }
+
+    /// Inlines the statements of a definition, lowering its inputs with the labels of the
+    /// caller. Returns the nodes placed into the flow along with the values of its outputs.
+    fn lower_inlined(
+        &mut self,
+        name: &str,
+        inputs: Vec<Input>,
+        callbacks: Vec<Callback>,
+    ) -> Result<(Vec<NodeId>, Vec<Endpoint>)> {
+        if self.inlined.iter().any(|inlining| inlining.name == name) {
+            return Err(anyhow!("Recursive definition `{}` can't be transpiled yet!", name));
+        }
+        let definition = self.definitions[name].clone();
+
+        let mut labels = HashMap::new();
+        for (i, input) in inputs.into_iter().enumerate() {
+            let parameter = match input.label {
+                Some(label) if definition.inputs.contains(&label) => label,
+                Some(label) => return Err(anyhow!("`{}` has no input `{}`!", name, label)),
+                None => match definition.inputs.get(i) {
+                    Some(parameter) => parameter.clone(),
+                    None => return Err(anyhow!("Too many inputs were provided to `{}`!", name)),
+                },
+            };
+            if let Some(endpoint) = self.lower_expression(input.value)? {
+                labels.insert(parameter, endpoint);
+            }
+        }
+
+        let mut passed = HashMap::new();
+        for (i, callback) in callbacks.into_iter().enumerate() {
+            let label = match callback.label {
+                Some(label) if definition.callbacks.contains(&label) => label,
+                Some(label) => return Err(anyhow!("`{}` has no callback `{}`!", name, label)),
+                None => match definition.callbacks.get(i) {
+                    Some(label) => label.clone(),
+                    None => return Err(anyhow!("`{}` has no callback at position {}!", name, i)),
+                },
+            };
+            if !callback.outputs.is_empty() {
+                return Err(anyhow!("Callback `{}` of `{}` has no outputs!", label, name));
+            }
+            passed.insert(label, callback.statements);
+        }
+
+        let caller = std::mem::replace(&mut self.labels, labels);
+        self.inlined.push(Inlining {
+            name: name.to_string(),
+            callbacks: passed,
+            labels: caller,
+        });
+        let flow = self.lower_block(definition.statements);
+        let Some(inlining) = self.inlined.pop() else {
+            unreachable!();
+        };
+        let labels = std::mem::replace(&mut self.labels, inlining.labels);
+        let flow = flow?;
+
+        let results = definition
+            .outputs
+            .iter()
+            .map(|output| {
+                labels
+                    .get(output)
+                    .copied()
+                    .ok_or_else(|| anyhow!("`{}` never assigns its output `{}`!", name, output))
+            })
+            .collect::<Result<_>>()?;
+        Ok((flow, results))
+    }
+
+    /// Whether the name is a callback passed to the definition being inlined.
+    fn is_passed_callback(&self, name: &str) -> bool {
+        self.inlined
+            .last()
+            .is_some_and(|inlining| inlining.callbacks.contains_key(name))
+    }
+
+    /// Lowers the statements the caller passed as a callback in its own scope, returning the
+    /// nodes placed into the flow.
+    fn lower_passed_callback(&mut self, name: &str) -> Result<Vec<NodeId>> {
+        let Some(mut inlining) = self.inlined.pop() else {
+            unreachable!();
+        };
+        let statements = inlining.callbacks[name].clone();
+        std::mem::swap(&mut self.labels, &mut inlining.labels);
+        let flow = self.lower_block(statements);
+        std::mem::swap(&mut self.labels, &mut inlining.labels);
+        self.inlined.push(inlining);
+        flow
+    }
+
+    fn lower_callbacks(&mut self, node: NodeId, callbacks: Vec<Callback>, span: &Span) -> Result<()> {
+        for (i, callback) in callbacks.into_iter().enumerate() {
+            let prefab = self.prefab(node)?;
+            let port = match &callback.label {
+                Some(label) => prefab.callbacks.iter().position(|port| &port.name == label),
+                None => (i < prefab.callbacks.len()).then_some(i),
+            };
+            let Some(port) = port else {
+                return Err(anyhow!(
+                    "Can't find callback {} of prefab with name: {}",
+                    callback.label.unwrap_or_else(|| i.to_string()),
+                    prefab.name
+                ));
+            };
+
+            // Variables among the outputs are written before the rest of the callback runs.
+            let mut flow = self.bind_outputs(node, &callback.outputs, span)?;
+            flow.extend(self.lower_block(callback.statements)?);
+            if let Some(head) = self.chain(&flow) {
+                self.graph.connect(
+                    WireKind::Execute,
+                    Endpoint { node, port: port + 1 },
+                    Endpoint { node: head, port: 0 },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Binds the outputs of a node to labels or writes them to variables, returning the nodes
+    /// doing the writing.
+    fn bind_outputs(&mut self, node: NodeId, outputs: &[Output], span: &Span) -> Result<Vec<NodeId>> {
+        let mut flow = Vec::new();
+        for (port, output) in outputs.iter().enumerate() {
+            flow.extend(self.bind_output(Endpoint { node, port }, output, span)?);
+        }
+        Ok(flow)
+    }
+
+    fn bind_output(&mut self, endpoint: Endpoint, output: &Output, span: &Span) -> Result<Option<NodeId>> {
+        let Some(name) = &output.name else {
+            return Ok(None);
+        };
+        if let Some(member) = &output.member {
+            let object = self.lower_target(output)?;
+            if raw_kind(self.output_kind(object)?) != RawKind::Object {
+                let value = self.lower_component(object, member, endpoint)?;
+                let whole = Output {
+                    member: None,
+                    ..output.clone()
+                };
+                return self.bind_output(value, &whole, span);
+            }
+            return self.lower_setter(object, member, endpoint, span).map(Some);
+        }
+        if output.index.is_some() {
+            let pointer = self.lower_target(output)?;
+            return self.lower_store(pointer, endpoint, span).map(Some);
+        }
+        let Some(modifier) = &output.modifier else {
+            self.labels.insert(name.clone(), endpoint);
+            return Ok(None);
+        };
+
+        let kind = raw_kind(self.output_kind(endpoint)?);
+
+        let mut node = Node::new(&format!("set_{}", kind_name(kind)));
+        node.options.push((0, OptData::Name(variable_name(modifier, name))));
+        node.span = Some(span.clone());
+        let node = self.graph.add_node(node);
+        self.graph.connect(
+            WireKind::Value(ValueKind::Raw(kind)),
+            endpoint,
+            Endpoint { node, port: 0 },
+        );
+        Ok(Some(node))
+    }
+
+    /// Writes the items into consecutive elements of the list an output points to, leaving
+    /// skipped items unchanged.
+    fn lower_list(&mut self, items: Vec<Expression>, outputs: &[Output], span: &Span) -> Result<Vec<NodeId>> {
+        let [output] = outputs else {
+            return Err(anyhow!("Can only assign a list to a single variable, like `$xs = [1, 2]`!"));
+        };
+        if output.modifier.is_none() && output.index.is_none() {
+            return Err(anyhow!(
+                "Can't assign a list to label `{}`, only to variables like `$xs = [1, 2]`!",
+                output.name.as_deref().unwrap_or("_")
+            ));
+        }
+
+        let pointer = self.lower_target(output)?;
+        let mut flow = Vec::new();
+        for (i, item) in items.into_iter().enumerate() {
+            let Some(value) = self.lower_expression(item)? else {
+                continue;
+            };
+            let element = match i {
+                0 => pointer,
+                i => self.lower_index(pointer, Expression::Integer(i as i32))?,
+            };
+            flow.push(self.lower_store(element, value, span)?);
+        }
+        Ok(flow)
+    }
+
+    /// Lowers adding or subtracting one from a number variable or list element, like `$x += 1`,
+    /// to changing it in place.
+    fn lower_step(&mut self, value: &Expression, outputs: &[Output], span: &Span) -> Result<Option<NodeId>> {
+        let ([output], Expression::Call { name, inputs }) = (outputs, value) else {
+            return Ok(None);
+        };
+        let prefab = match name.as_str() {
+            "add" => "increase_number",
+            "subtract" => "decrease_number",
+            _ => return Ok(None),
+        };
+        let [target, step] = &inputs[..] else {
+            return Ok(None);
+        };
+        let is_one = matches!(step.value, Expression::Integer(1)) || step.value == Expression::Float(1.0);
+        let is_number = match (&output.modifier, &output.name) {
+            (Some(modifier), Some(name)) => self.variables.get(&variable_name(modifier, name)) == Some(&RawKind::Number),
+            (None, Some(name)) => match self.labels.get(name) {
+                Some(&endpoint) => self.output_kind(endpoint)? == ValueKind::Reference(RawKind::Number),
+                None => false,
+            },
+            _ => false,
+        };
+        let is_pointer = output.member.is_none() && (output.modifier.is_some() || output.index.is_some());
+        if !is_one || !is_number || !is_pointer || output.to_expression().as_ref() != Some(&target.value) {
+            return Ok(None);
+        }
+
+        let pointer = self.lower_target(output)?;
+        let mut node = Node::new(prefab);
+        node.span = Some(span.clone());
+        let node = self.graph.add_node(node);
+        self.graph.connect(
+            WireKind::Value(ValueKind::Reference(RawKind::Number)),
+            pointer,
+            Endpoint { node, port: 0 },
+        );
+        Ok(Some(node))
+    }
+
+    /// Lowers the pointer to the variable or list element an output writes to, or that holds the
+    /// object whose property it writes to.
+    fn lower_target(&mut self, output: &Output) -> Result<Endpoint> {
+        let Some(name) = &output.name else {
+            return Err(anyhow!("Can't write to a skipped output!"));
+        };
+        let variable = Expression::Variable {
+            modifier: output.modifier.clone(),
+            name: name.clone(),
+        };
+        let Some(pointer) = self.lower_expression(variable)? else {
+            unreachable!();
+        };
+        match &output.index {
+            Some(index) => self.lower_index(pointer, index.clone()),
+            None => Ok(pointer),
+        }
+    }
+
+    /// Adds a node pointing to an element of the list, counting from the given pointer.
+    fn lower_index(&mut self, list: Endpoint, index: Expression) -> Result<Endpoint> {
+        let ValueKind::Reference(kind) = self.output_kind(list)? else {
+            return Err(anyhow!("Can only index variables and list elements, like `$xs[i]`!"));
+        };
+        let index = self.lower_expression(index)?;
+
+        let node = self.graph.add_node(Node::new(&format!("list_{}", kind_name(kind))));
+        self.graph.connect(
+            WireKind::Value(ValueKind::Reference(kind)),
+            list,
+            Endpoint { node, port: 0 },
+        );
+        if let Some(index) = index {
+            self.graph.connect(
+                WireKind::Value(ValueKind::Raw(RawKind::Number)),
+                index,
+                Endpoint { node, port: 1 },
+            );
+        }
+        Ok(Endpoint { node, port: 0 })
+    }
+
+    /// Adds a node writing the value to the variable or list element the pointer points to.
+    fn lower_store(&mut self, pointer: Endpoint, value: Endpoint, span: &Span) -> Result<NodeId> {
+        let ValueKind::Reference(kind) = self.output_kind(pointer)? else {
+            unreachable!();
+        };
+
+        let mut node = Node::new(&format!("set_{}_list", kind_name(kind)));
+        node.span = Some(span.clone());
+        let node = self.graph.add_node(node);
+        self.graph.connect(
+            WireKind::Value(ValueKind::Reference(kind)),
+            pointer,
+            Endpoint { node, port: 0 },
+        );
+        self.graph.connect(
+            WireKind::Value(ValueKind::Raw(kind)),
+            value,
+            Endpoint { node, port: 1 },
+        );
+        Ok(node)
+    }
+
+    /// Selects components of a vector or rotation. Each access places its own break node, which
+    /// get merged with the others breaking the same value.
+    fn lower_member(&mut self, value: Endpoint, member: &str) -> Result<Endpoint> {
+        let kind = raw_kind(self.output_kind(value)?);
+        let (split, join) = match kind {
+            RawKind::Vector => ("break_vector", "make_vector"),
+            RawKind::Rotation => ("break_rotation", "make_rotation"),
+            RawKind::Object => return self.lower_getter(value, member),
+            kind => return Err(anyhow!("Can't access `.{}` of a {:?}!", member, kind)),
+        };
+        let Some(components) = swizzle(member) else {
+            return Err(anyhow!(
+                "Unknown member `.{}` of a {:?}, expected components like `.x` or `.xz`!",
+                member,
+                kind
+            ));
+        };
+
+        let split = self.graph.add_node(Node::new(split));
+        self.graph.connect(
+            WireKind::Value(ValueKind::Raw(kind)),
+            value,
+            Endpoint { node: split, port: 0 },
+        );
+        if let [component] = components[..] {
+            return Ok(Endpoint {
+                node: split,
+                port: component,
+            });
+        }
+
+        // Components left out stay unconnected, which makes them zero.
+        let join = self.graph.add_node(Node::new(join));
+        for component in components {
+            self.graph.connect(
+                WireKind::Value(ValueKind::Raw(RawKind::Number)),
+                Endpoint {
+                    node: split,
+                    port: component,
+                },
+                Endpoint {
+                    node: join,
+                    port: component,
+                },
+            );
+        }
+        Ok(Endpoint { node: join, port: 0 })
+    }
+
+    /// Builds a copy of the vector or rotation with the components of the member replaced by the
+    /// ones of the value, like `(5, v.y, v.z)` for `v.x = 5`.
+    fn lower_component(&mut self, current: Endpoint, member: &str, value: Endpoint) -> Result<Endpoint> {
+        let kind = raw_kind(self.output_kind(current)?);
+        let (split, join) = match kind {
+            RawKind::Vector => ("break_vector", "make_vector"),
+            RawKind::Rotation => ("break_rotation", "make_rotation"),
+            kind => return Err(anyhow!("Can't assign to `.{}` of a {:?}!", member, kind)),
+        };
+        let Some(components) = swizzle(member) else {
+            return Err(anyhow!(
+                "Unknown member `.{}` of a {:?}, expected components like `.x` or `.xz`!",
+                member,
+                kind
+            ));
+        };
+
+        // A single component is assigned a number, several ones take theirs from a whole value.
+        let parts = match components[..] {
+            [_] => None,
+            _ => {
+                let node = self.graph.add_node(Node::new(split));
+                self.graph.connect(
+                    WireKind::Value(ValueKind::Raw(kind)),
+                    value,
+                    Endpoint { node, port: 0 },
+                );
+                Some(node)
+            }
+        };
+        let old = self.graph.add_node(Node::new(split));
+        self.graph.connect(
+            WireKind::Value(ValueKind::Raw(kind)),
+            current,
+            Endpoint { node: old, port: 0 },
+        );
+        let join = self.graph.add_node(Node::new(join));
+        for component in 0..3 {
+            let from = match parts {
+                _ if !components.contains(&component) => Endpoint {
+                    node: old,
+                    port: component,
+                },
+                Some(node) => Endpoint { node, port: component },
+                None => value,
+            };
+            self.graph.connect(
+                WireKind::Value(ValueKind::Raw(RawKind::Number)),
+                from,
+                Endpoint {
+                    node: join,
+                    port: component,
+                },
+            );
+        }
+        Ok(Endpoint { node: join, port: 0 })
+    }
+
+    /// Reads a property of an object from the output of its getter named like it.
+    fn lower_getter(&mut self, object: Endpoint, member: &str) -> Result<Endpoint> {
+        let Some(property) = property(member) else {
+            return Err(unknown_property(member));
+        };
+
+        let node = self.graph.add_node(Node::new(property.getter));
+        self.graph.connect(
+            WireKind::Value(ValueKind::Raw(RawKind::Object)),
+            object,
+            Endpoint { node, port: 0 },
+        );
+        let Some(port) = self.prefab(node)?.outputs.iter().position(|port| port.name == member) else {
+            unreachable!();
+        };
+        Ok(Endpoint { node, port })
+    }
+
+    /// Adds a node writing the value to a property of an object through the input of its setter
+    /// named like it. The other properties the setter writes are passed their current value, as
+    /// unconnected inputs would reset them.
+    fn lower_setter(&mut self, object: Endpoint, member: &str, value: Endpoint, span: &Span) -> Result<NodeId> {
+        let Some(property) = property(member) else {
+            return Err(unknown_property(member));
+        };
+
+        let mut node = Node::new(property.setter);
+        node.span = Some(span.clone());
+        let node = self.graph.add_node(node);
+        let Some(port) = self.prefab(node)?.inputs.iter().position(|port| port.name == member) else {
+            unreachable!();
+        };
+        self.graph.connect(
+            WireKind::Value(ValueKind::Raw(RawKind::Object)),
+            object,
+            Endpoint { node, port: 0 },
+        );
+        self.graph.connect(
+            WireKind::Value(ValueKind::Raw(property.kind)),
+            value,
+            Endpoint { node, port },
+        );
+
+        let siblings = self.prefab(node)?.inputs[1..]
+            .iter()
+            .enumerate()
+            .map(|(i, input)| (i + 1, input.name.clone(), input.kind))
+            .filter(|&(sibling, _, _)| sibling != port)
+            .collect::<Vec<_>>();
+        for (sibling, name, kind) in siblings {
+            let current = self.lower_getter(object, &name)?;
+            self.graph.connect(WireKind::Value(kind), current, Endpoint { node, port: sibling });
+        }
+        Ok(node)
+    }
+
+    fn lower_expression(&mut self, expression: Expression) -> Result<Option<Endpoint>> {
+        let node = match expression {
+            Expression::Skip => return Ok(None),
+            Expression::Float(value) => self.lower_call(
+                "number",
+                vec![Input {
+                    label: None,
+                    value: Expression::Float(value),
+                }],
+            )?,
+            Expression::Integer(value) => return self.lower_expression(Expression::Float(value as f64)),
+            Expression::Boolean(value) => self.lower_call(&value.to_string(), Vec::new())?,
+            Expression::Vector(value) => self.lower_call(
+                "vector",
+                vec![Input {
+                    label: None,
+                    value: Expression::Vector(value),
+                }],
+            )?,
+            Expression::Rotation(value) => self.lower_call(
+                "rotation",
+                vec![Input {
+                    label: None,
+                    value: Expression::Rotation(value),
+                }],
+            )?,
+            Expression::String(value) => {
+                return Err(anyhow!("Can't parse string literal to expression: {}", value))
+            }
+            Expression::Call { name, inputs } if self.definitions.contains_key(&name) => {
+                let (flow, results) = self.lower_inlined(&name, inputs, Vec::new())?;
+                if !flow.is_empty() {
+                    return Err(anyhow!(
+                        "`{}` executes nodes, so it can only be called as a statement!",
+                        name
+                    ));
+                }
+                return match results.first() {
+                    Some(&result) => Ok(Some(result)),
+                    None => Err(anyhow!("`{}` has no outputs to use as a value!", name)),
+                };
+            }
+            Expression::Call { name, inputs } => self.lower_call(&name, inputs)?,
+            method @ Expression::Method { .. } => return self.lower_expression(method.into_call()),
+            Expression::List(_) => {
+                return Err(anyhow!(
+                    "Lists can only be assigned to variables, like `$xs = [1, 2]`!"
+                ))
+            }
+            Expression::Member { value, member } => {
+                let Some(value) = self.lower_expression(*value)? else {
+                    return Err(anyhow!("Can't access `.{}` of a skipped value!", member));
+                };
+                return self.lower_member(value, &member).map(Some);
+            }
+            Expression::Index { list, index } => {
+                let Some(list) = self.lower_expression(*list)? else {
+                    return Err(anyhow!("Can't index a skipped value!"));
+                };
+                return self.lower_index(list, *index).map(Some);
+            }
+            Expression::Reference(variable) => {
+                let Expression::Variable { modifier, name } = &*variable else {
+                    return Err(anyhow!("Can only take references to variables!"));
+                };
+                let name = match modifier {
+                    Some(modifier) => variable_name(modifier, name),
+                    None => name.clone(),
+                };
+                let Some(endpoint) = self.lower_expression(*variable)? else {
+                    unreachable!();
+                };
+                let ValueKind::Reference(_) = self.output_kind(endpoint)? else {
+                    return Err(anyhow!("Can't take a reference to `{}` as it's not a variable!", name));
+                };
+                return Ok(Some(endpoint));
+            }
+            Expression::Variable { modifier: None, name } => {
+                return match self.labels.get(&name) {
+                    Some(&endpoint) => Ok(Some(endpoint)),
+                    None => Err(anyhow!("Can't find label with name: {}", name)),
+                }
+            }
+            Expression::Variable {
+                modifier: Some(modifier),
+                name,
+            } => {
+                let name = variable_name(&modifier, &name);
+                let Some(&kind) = self.variables.get(&name) else {
+                    return Err(anyhow!(
+                        "Can't infer the type of variable `{}` as it's never assigned!",
+                        name
+                    ));
+                };
+                let mut node = Node::new(&format!("get_{}", kind_name(kind)));
+                node.options.push((0, OptData::Name(name)));
+                self.graph.add_node(node)
+            }
+        };
+        Ok(Some(Endpoint { node, port: 0 }))
+    }
+
+    /// Adds a node for the prefab, lowering its inputs before the node itself.
+    fn lower_call(&mut self, name: &str, inputs: Vec<Input>) -> Result<NodeId> {
+        if is_operator(name) {
+            return self.lower_operator(name, inputs);
+        }
+        let Some(prefab) = self.prefabs.get(name) else {
+            return Err(anyhow!("Can't find prefab with name: {}", name));
+        };
+
+        let mut node = Node::new(name);
+        let mut edges = Vec::new();
+        for (i, input) in inputs.into_iter().enumerate() {
+            if i < prefab.inputs.len() {
+                // Variables changed in place have to be marked as such with `&`.
+                let is_reference = matches!(input.value, Expression::Reference(_));
+                if let Some(from) = self.lower_expression(input.value)? {
+                    let port = &prefab.inputs[i];
+                    if let ValueKind::Reference(kind) = port.kind {
+                        if !is_reference || self.output_kind(from)? != port.kind {
+                            return Err(anyhow!(
+                                "Input `{}` of `{}` needs a reference to a {} variable, like `&$name`!",
+                                port.name,
+                                name,
+                                kind_name(kind)
+                            ));
+                        }
+                    } else if is_reference {
+                        return Err(anyhow!(
+                            "Input `{}` of `{}` takes a {} value, not a reference, so drop the `&`!",
+                            port.name,
+                            name,
+                            kind_name(raw_kind(port.kind))
+                        ));
+                    }
+                    edges.push((WireKind::Value(port.kind), from, i));
+                }
+            } else {
+                let i = i - prefab.inputs.len();
+                if i < prefab.options.len() {
+                    let data = lower_option(&prefab.options[i], &input.value)?;
+                    node.options.push((i as u8, data));
+                } else {
+                    return Err(anyhow!("Too many inputs were provided!"));
+                }
+            }
+        }
+
+        let node = self.graph.add_node(node);
+        for (kind, from, port) in edges {
+            self.graph.connect(kind, from, Endpoint { node, port });
+        }
+        Ok(node)
+    }
+
+    /// Adds a node for the prefab an operator works with on the types of its operands, followed by
+    /// a `not` node if its result has to be negated.
+    fn lower_operator(&mut self, name: &str, inputs: Vec<Input>) -> Result<NodeId> {
+        let [a, b] = <[Input; 2]>::try_from(inputs).map_err(|_| anyhow!("`{}` needs two operands!", name))?;
+        let (Some(a), Some(b)) = (self.lower_expression(a.value)?, self.lower_expression(b.value)?) else {
+            return Err(anyhow!("Can't skip an operand of `{}`!", name));
+        };
+        let kinds = (raw_kind(self.output_kind(a)?), raw_kind(self.output_kind(b)?));
+        let Some(operation) = resolve_operator(name, kinds.0, kinds.1) else {
+            return Err(anyhow!(
+                "Can't use `{}` on a {:?} and a {:?}!",
+                operator_symbol(name),
+                kinds.0,
+                kinds.1
+            ));
+        };
+        let operands = match operation.swapped {
+            false => [a, b],
+            true => [b, a],
+        };
+
+        let node = self.graph.add_node(Node::new(operation.prefab));
+        let ports = self.prefab(node)?.inputs.iter().map(|port| port.kind).collect::<Vec<_>>();
+        for (port, from) in operands.into_iter().enumerate() {
+            self.graph.connect(WireKind::Value(ports[port]), from, Endpoint { node, port });
+        }
+        if !operation.negated {
+            return Ok(node);
+        }
+
+        let not = self.graph.add_node(Node::new("not"));
+        self.graph.connect(
+            WireKind::Value(ValueKind::Raw(RawKind::Truth)),
+            Endpoint { node, port: 0 },
+            Endpoint { node: not, port: 0 },
+        );
+        Ok(not)
+    }
+
+    fn output_kind(&self, endpoint: Endpoint) -> Result<ValueKind> {
+        let prefab = self.prefab(endpoint.node)?;
+        match prefab.outputs.get(endpoint.port) {
+            Some(port) => Ok(port.kind),
+            None => Err(anyhow!(
+                "Prefab `{}` has no output at position {}",
+                prefab.name,
+                endpoint.port
+            )),
+        }
+    }
+
+    fn prefab(&self, node: NodeId) -> Result<&Prefab> {
+        let name = &self.graph.nodes[node].prefab;
+        self.prefabs
+            .get(name)
+            .ok_or_else(|| anyhow!("Can't find prefab with name: {}", name))
+    }
+}
+
+fn unknown_property(member: &str) -> anyhow::Error {
+    anyhow!(
+        "Unknown property `.{}` of an object, expected `.position`, `.rotation`, `.velocity` or `.spin`!",
+        member
+    )
+}
+
+pub fn lower_option(opt: &prefabs::Opt, value: &Expression) -> Result<OptData> {
+    let data = match (&opt.kind, value) {
+        (OptKind::Int8, &Expression::Integer(value)) => OptData::Int8(value.try_into()?),
+        (OptKind::Int8, &Expression::Boolean(value)) => OptData::Int8(value.into()),
+        (OptKind::Int16, &Expression::Integer(value)) => OptData::Int16(value.try_into()?),
+        (OptKind::Float32, &Expression::Integer(value)) => OptData::Float32(value as f32),
+        (OptKind::Float32, &Expression::Float(value)) => OptData::Float32(value as f32),
+        (OptKind::Vec, &(Expression::Vector([x, y, z]) | Expression::Rotation([x, y, z]))) => {
+            OptData::Vec([x as f32, y as f32, z as f32])
+        }
+        (OptKind::Name, Expression::String(value)) => OptData::Name(value.to_string()),
+        (kind, value) => {
+            return Err(anyhow!(
+                "Option `{}` can't be set to `{}`, it needs a constant {:?}!",
+                opt.name,
+                format_expression(value),
+                kind
+            ))
+        }
+    };
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use crate::{
+        game::OptData,
+        parser::parse_source,
+        transpiler::{build_graph, graph::Graph, prefabs::get_prefabs, TranspileError},
+    };
+
+    fn build(source: &str) -> anyhow::Result<Graph> {
+        build_graph(parse_source(source).unwrap(), &get_prefabs(), &mut Vec::new())
+    }
+
+    #[test]
+    fn rejects_unused_lists_assigned_to_labels() {
+        let error = build("xs = [1, 2]").unwrap_err();
+        assert_eq!(error.to_string(), "Can't assign a list to label `xs`, only to variables like `$xs = [1, 2]`!");
+    }
+
+    #[test]
+    fn locates_errors_at_their_statement() {
+        let source = "x = 1\nif True {\n  y = z\n}";
+        let error = build(source).unwrap_err();
+        let span = error.downcast_ref::<TranspileError>().and_then(|error| error.span.clone());
+        assert_eq!(&source[span.unwrap()], "y = z");
+    }
+
+    #[test]
+    fn rejects_options_that_are_not_constant() {
+        let error = build("win(delay: 1.5)").unwrap_err();
+        assert_eq!(error.to_string(), "Option `delay` can't be set to `1.5`, it needs a constant Int8!");
+    }
+
+    #[test]
+    fn requires_references_to_be_marked() {
+        let error = build("$coins = 0\nincrease_number($coins)").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Input `variable` of `increase_number` needs a reference to a number variable, like `&$name`!"
+        );
+        build("$coins = 0\nincrease_number(&$coins)").unwrap();
+
+        let error = build("$coins = 0\ninspect_number(&$coins)").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Input `number` of `inspect_number` takes a number value, not a reference, so drop the `&`!"
+        );
+    }
+
+    #[test]
+    fn keeps_other_properties_when_setting_one() {
+        let graph = build("player = shrub()\nplayer.rotation = rot(0, 90, 0)").unwrap();
+        let setter = graph.nodes.iter().position(|node| node.prefab == "set_position").unwrap();
+        let position = graph
+            .inputs(setter)
+            .into_iter()
+            .find(|edge| edge.to.port == 1)
+            .expect("position should stay connected");
+        assert_eq!(graph.nodes[position.from.node].prefab, "get_position");
+        assert_eq!(position.from.port, 0);
+    }
+
+    #[test]
+    fn assigns_components_of_vectors() {
+        let graph = build("$v = vec(1, 2, 3)\n$v.x = 5").unwrap();
+        let join = graph.nodes.iter().position(|node| node.prefab == "make_vector").unwrap();
+        let sources = graph
+            .inputs(join)
+            .into_iter()
+            .sorted_by_key(|edge| edge.to.port)
+            .map(|edge| (graph.nodes[edge.from.node].prefab.as_str(), edge.from.port))
+            .collect::<Vec<_>>();
+        assert_eq!(sources, [("number", 0), ("break_vector", 1), ("break_vector", 2)]);
+        let setters = graph.nodes.iter().filter(|node| node.prefab == "set_vector").count();
+        assert_eq!(setters, 2);
+
+        let error = build("$n = 1\n$n.x = 5").unwrap_err();
+        assert_eq!(error.to_string(), "Can't assign to `.x` of a Number!");
+    }
+
+    #[test]
+    fn resolves_operators_on_both_operands() {
+        let graph = build("$v = vec(1, 2, 3)\n$a = 2 * $v\n$b = $v * 3").unwrap();
+        let scales = graph.nodes.iter().filter(|node| node.prefab == "scale").count();
+        assert_eq!(scales, 2);
+        assert!(!graph.nodes.iter().any(|node| node.prefab == "multiply"));
+
+        let error = build("$v = vec(1, 2, 3)\n$a = $v * $v").unwrap_err();
+        assert_eq!(error.to_string(), "Can't use `*` on a Vector and a Vector!");
+    }
+
+    #[test]
+    fn infers_variables_accumulating_into_themselves() {
+        let graph = build("$count += 2\n$offset = $offset + vec(0, 1, 0)").unwrap();
+        let prefabs = graph.nodes.iter().map(|node| node.prefab.as_str()).collect::<Vec<_>>();
+        assert!(prefabs.contains(&"add_numbers"));
+        assert!(prefabs.contains(&"add_vectors"));
+    }
+
+    #[test]
+    fn infers_operands_assigned_later() {
+        let graph = build("$a = 2 * $v\n$v = vec(1, 2, 3)").unwrap();
+        assert!(graph.nodes.iter().any(|node| node.prefab == "scale"));
+        assert!(graph.nodes.iter().any(|node| node.prefab == "set_vector"));
+    }
+
+    #[test]
+    fn resolves_comparisons_to_prefabs() {
+        let graph = build("$score = 0\n!best = max(!best, $score)\nif(!best != $score) true {\n  win()\n}").unwrap();
+        let prefabs = graph.nodes.iter().map(|node| node.prefab.as_str()).collect::<Vec<_>>();
+        assert!(prefabs.contains(&"equal_numbers"));
+        assert!(prefabs.contains(&"not"));
+
+        let error = build("$v = vec(1, 2, 3)\n$a = $v <= $v").unwrap_err();
+        assert_eq!(error.to_string(), "Can't use `<=` on a Vector and a Vector!");
+    }
+
+    #[test]
+    fn lowers_events_to_sensors() {
+        let graph = build(
+            "player = shrub()
+            on play {
+                $score = 0
+            }
+            on touch |x, y| {
+                $score = x + y
+            }
+            on swipe |direction| {
+                $swipe = direction
+            }
+            on button \"Jump\" {
+                $jumps += 2
+            }
+            on collision(player) |other, impulse| {
+                $impulse = impulse
+            }",
+        )
+        .unwrap();
+        let node = |prefab: &str| {
+            graph
+                .nodes
+                .iter()
+                .position(|node| node.prefab == prefab)
+                .unwrap_or_else(|| panic!("no `{}` node", prefab))
+        };
+
+        for sensor in ["play_sensor", "touch_sensor", "swipe_sensor", "button", "collision"] {
+            let callbacks = graph.successors(node(sensor)).into_iter().filter(|edge| edge.from.port > 0);
+            assert_eq!(callbacks.count(), 1, "`{}` runs its statements", sensor);
+        }
+        let touched = graph.outputs(node("touch_sensor"));
+        assert_eq!(touched.iter().map(|edge| edge.from.port).sorted().collect::<Vec<_>>(), [0, 1]);
+        assert!(touched.iter().all(|edge| graph.nodes[edge.to.node].prefab == "add_numbers"));
+        assert_eq!(graph.outputs(node("swipe_sensor"))[0].from.port, 0);
+        assert_eq!(graph.outputs(node("collision"))[0].from.port, 1);
+        assert_eq!(graph.inputs(node("collision"))[0].from.node, node("shrub"));
+        assert!(matches!(&graph.nodes[node("button")].options[..], [(0, OptData::Name(label))] if label == "Jump"));
+    }
+
+    #[test]
+    fn rejects_recursive_definitions() {
+        let error = build("def f() {\n  f()\n}\nf()").unwrap_err();
+        assert_eq!(error.to_string(), "Recursive definition `f` can't be transpiled yet!");
+    }
+
+    #[test]
+    fn rejects_definitions_executing_nodes_as_values() {
+        let error = build("def f() |x| {\n  $y = 1\n  x = 2\n}\ninspect_number(f())").unwrap_err();
+        assert_eq!(error.to_string(), "`f` executes nodes, so it can only be called as a statement!");
+    }
+}
@@ -1,238 +1,42 @@
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use itertools::Itertools;
 use ndarray::Array3;
 use std::{
     cmp::{max, min},
     collections::HashMap,
+    error::Error,
+    fmt::Display,
 };
 
 use crate::{
-    game::{self, Chunk, Collider, Game, Kind, OptData, OptKind},
-    parser::grammar::{Expression, Input, Statement},
-    transpiler::prefabs::{get_prefabs, Prefab},
+    game::{self, Chunk, Collider, Game, Kind, OptData},
+    parser::grammar::{Span, Statement},
+    transpiler::{
+        graph::Graph,
+        prefabs::{get_prefabs, Prefab},
+    },
 };
 
 mod blocks;
+pub mod budget;
+mod cse;
+mod dead;
+pub mod dot;
+pub mod fold;
+pub mod graph;
+mod infer;
+mod layout;
+mod lower;
 mod opts;
-mod prefabs;
+pub mod prefabs;
+pub mod raise;
 mod wires;
 
-pub fn transpile_statements(statements: Vec<Statement>, ctx: &mut Context, prefabs: &HashMap<String, Prefab>) -> Result<()> {
-    let mut prev = None::<Block>;
-    for statement in statements {
-        let new = transpile_statement(statement, ctx, prefabs)?;
-
-        if let Some(new) = new {
-            if let Some(ref prev) = prev {
-                ctx.wires.push(Wire {
-                    from: Port {
-                        position: prev.position,
-                        offset: [0x03, 0o01, 0o00],
-                    },
-                    to: Port {
-                        position: new.position,
-                        offset: [0o03, 0o01, 0o06 + (new.parts.dim().0 as u16 - 1) * 8],
-                    },
-                })
-            }
-            prev = Some(new);
-        }
-    }
-    Ok(())
-}
-
-pub fn transpile_statement(statement: Statement, ctx: &mut Context, prefabs: &HashMap<String, Prefab>) -> Result<Option<Block>> {
-    match statement {
-        Statement::Invocation {
-            name,
-            inputs,
-            outputs,
-            callbacks,
-        } => {
-            let Some(prefab) = prefabs.get(&name) else {
-                return Err(anyhow!("Can't find prefab with name: {}", name));
-            };
-
-            let height = prefab.parts.dim().0 as i32;
-            let mut pos = ctx.pos.clone();
-            ctx.pos[0] -= 1;
-            pos[2] -= height;
-
-            let block = Block {
-                parts: prefab.parts.clone(),
-                position: pos,
-            };
-            ctx.blocks.push(block.clone());
-
-            transpile_inputs(prefab, &block, inputs, ctx, prefabs)?;
-
-            ctx.pos[0] += 1;
-            ctx.pos[2] = min(ctx.pos[2], pos[2]);
-            Ok(Some(block))
-        }
-        Statement::Assignement { value, outputs } => todo!(),
-        Statement::Definition {
-            name,
-            inputs,
-            outputs,
-            callbacks,
-            statements,
-        } => todo!(),
-        Statement::Comment(value) => {
-            ctx.pos[2] -= 1;
-
-            for line in textwrap::wrap(value.as_str(), 16) {
-                let prefab = prefabs.get(&"comment".to_string()).unwrap();
-                let height = prefab.parts.dim().0 as i32;
-                ctx.pos[2] -= height;
-                ctx.pos[0] += 1;
-                ctx.blocks.push(Block {
-                    parts: prefab.parts.clone(),
-                    position: ctx.pos,
-                });
-                ctx.opts.push(Opt {
-                    data: OptData::Name(line.to_string()),
-                    position: ctx.pos,
-                    index: 0,
-                });
-                ctx.pos[0] -= 1;
-            }
-
-            Ok(None)
-        }
-    }
-}
-
-pub fn transpile_expression(expression: Expression, ctx: &mut Context, prefabs: &HashMap<String, Prefab>) -> Result<Option<Block>> {
-    match expression {
-        Expression::Skip => {
-            ctx.pos[2] -= 1;
-            Ok(None)
-        }
-        Expression::Float(value) => transpile_expression(
-            Expression::Call {
-                name: "number".to_string(),
-                inputs: vec![Input {
-                    label: None,
-                    value: Expression::Float(value),
-                }],
-            },
-            ctx,
-            prefabs
-        ),
-        Expression::Integer(value) => transpile_expression(Expression::Float(value as f64), ctx, prefabs),
-        Expression::Boolean(value) => transpile_expression(
-            Expression::Call {
-                name: value.to_string(),
-                inputs: Vec::new(),
-            },
-            ctx,
-            prefabs
-        ),
-        Expression::String(value) => {
-            return Err(anyhow!(
-                "Can't parse string literal to expression: {}",
-                value
-            ))
-        }
-        Expression::Call { name, inputs } => {
-            let Some(prefab) = prefabs.get(&name) else {
-                return Err(anyhow!("Can't find prefab with name: {}", name));
-            };
-
-            let height = prefab.parts.dim().0 as i32;
-            let width = prefab.parts.dim().2 as i32;
-            ctx.pos[0] -= width;
-            let mut pos = ctx.pos.clone();
-
-            ctx.pos[0] -= 1;
-            pos[2] -= height;
-
-            let block = Block {
-                parts: prefab.parts.clone(),
-                position: pos,
-            };
-            ctx.blocks.push(block.clone());
-
-            transpile_inputs(prefab, &block, inputs, ctx, prefabs)?;
-
-            ctx.pos[0] += width + 1;
-            ctx.pos[2] = min(ctx.pos[2], pos[2]);
-            Ok(Some(block))
-        }
-        Expression::Variable { modifier, name } => todo!(),
-    }
-}
-
-pub fn transpile_inputs(prefab: &Prefab, block: &Block, inputs: Vec<Input>, ctx: &mut Context, prefabs: &HashMap<String, Prefab>) -> Result<()> {
-    for (i, input) in inputs.into_iter().enumerate() {
-        if i < prefab.inputs.len() {
-            let prev = transpile_expression(input.value, ctx, prefabs)?;
-            if let Some(prev) = prev {
-                ctx.wires.push(Wire {
-                    from: Port {
-                        position: prev.position,
-                        offset: [
-                            0x06 + (prev.parts.dim().2 as u16 - 1) * 8,
-                            0o01,
-                            0o03 + (prev.parts.dim().0 as u16 - 1) * 8,
-                        ],
-                    },
-                    to: Port {
-                        position: block.position,
-                        offset: [0o00, 0o01, 0o03 + (block.parts.dim().0 as u16 - i as u16 - 1) * 8],
-                    },
-                })
-            }
-        } else {
-            let i = i - prefab.inputs.len();
-            if i < prefab.options.len() {
-                transpile_option( &prefab.options[i], &input.value, i as u8, block.position, ctx)?;
-            } else {
-                return Err(anyhow!("Too many inputs were provided!"))
-            }
-        }
-    }
-    Ok(())
-}
-
-pub fn transpile_option(opt: &prefabs::Opt, value: &Expression, index: u8,position: [i32; 3],  ctx: &mut Context) -> Result<()> {
-    let data = match opt.kind {
-        OptKind::Int8 => match value {
-            &Expression::Integer(value) => OptData::Int8(value.try_into()?),
-            &Expression::Boolean(value) => OptData::Int8(value.into()),
-            _ => unimplemented!(),
-        },
-        OptKind::Int16 => match value {
-            &Expression::Integer(value) => OptData::Int16(value.try_into()?),
-            _ => unimplemented!(),
-        },
-        OptKind::Float32 => match value {
-            &Expression::Integer(value) => OptData::Float32(value as f32),
-            &Expression::Float(value) => OptData::Float32(value as f32),
-            _ => unimplemented!(),
-        },
-        OptKind::Vec => todo!(),
-        OptKind::Name => match value {
-            Expression::String(value) => OptData::Name(value.to_string()),
-            _ => unimplemented!(),
-        },
-        _ => unimplemented!(),
-    };
-    
-    ctx.opts.push(Opt {
-        data,
-        index,
-        position
-    });
-    Ok(())
-}
-
-pub fn transpile_game(statements: Vec<Statement>) -> Result<Game> {
-    let mut ctx = Context::default();
+pub fn transpile_game(statements: Vec<Statement>, warnings: &mut Vec<Warning>) -> Result<Game> {
     let prefabs = get_prefabs();
 
-    transpile_statements(statements, &mut ctx, &prefabs)?;
+    let graph = build_graph(statements, &prefabs, warnings)?;
+    let ctx = layout::layout_graph(&graph, &prefabs)?;
 
     let size = Size::new(&ctx.blocks);
     let mut blocks: ndarray::ArrayBase<ndarray::OwnedRepr<u16>, ndarray::Dim<[usize; 3]>> =
@@ -299,6 +103,57 @@ pub fn transpile_game(statements: Vec<Statement>) -> Result<Game> {
     Ok(game)
 }
 
+/// Optimizes the statements and lowers them into a graph of prefab instances. Folding and dead
+/// code elimination still work on the statements, so their warnings point into the script, while
+/// common nodes are merged on the graph.
+pub fn build_graph(
+    statements: Vec<Statement>,
+    prefabs: &HashMap<String, Prefab>,
+    warnings: &mut Vec<Warning>,
+) -> Result<Graph> {
+    let statements = fold::fold_statements(statements);
+    let statements = dead::eliminate_dead_code(statements, prefabs, warnings);
+    let mut graph = lower::lower_program(statements, prefabs)?;
+    cse::eliminate_common_nodes(&mut graph, prefabs);
+    Ok(graph)
+}
+
+/// A problem that prevents transpiling the script, located at the statement it was found in.
+#[derive(Debug)]
+pub struct TranspileError {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl TranspileError {
+    /// Locates the error at the statement, unless a statement nested in it already did.
+    pub fn locate(error: anyhow::Error, span: Option<&Span>) -> anyhow::Error {
+        if error.is::<TranspileError>() {
+            return error;
+        }
+        TranspileError {
+            message: error.to_string(),
+            span: span.cloned(),
+        }
+        .into()
+    }
+}
+
+impl Display for TranspileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for TranspileError {}
+
+/// A problem in the script that doesn't prevent transpiling it.
+#[derive(Debug)]
+pub struct Warning {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
 #[derive(Debug, Default)]
 pub struct Context {
     pub pos: [i32; 3],
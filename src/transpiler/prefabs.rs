@@ -14,6 +14,34 @@ pub struct Prefab {
     pub callable: bool,
     pub callbacks: Vec<ExecutePort>,
     pub options: Vec<Opt>,
+    /// Whether instances with the same inputs can give different outputs, because each of them
+    /// holds state of its own, like `random` or a placed motor, or because it reads sensors or the
+    /// world, which can change while the frame runs. Executed prefabs, like the touch and swipe
+    /// sensors, are never shared and don't need the flag.
+    pub stateful: bool,
+    /// Whether the prefab ends the game, like `win` and `lose`. Nodes executed after it still
+    /// run while its delay counts down.
+    pub terminal: bool,
+}
+
+impl Prefab {
+    /// Whether the prefab places an object into the level instead of computing a value.
+    pub fn is_object(&self) -> bool {
+        self.inputs.is_empty()
+            && self.options.is_empty()
+            && matches!(
+                &self.outputs[..],
+                [ValuePort {
+                    kind: ValueKind::Raw(RawKind::Object),
+                    ..
+                }]
+            )
+    }
+
+    /// Whether sharing the outputs of a single instance behaves the same as placing it repeatedly.
+    pub fn is_pure(&self) -> bool {
+        !self.callable && self.callbacks.is_empty() && !self.stateful && !self.is_object()
+    }
 }
 
 #[derive(Debug)]
@@ -821,6 +849,7 @@ pub fn get_prefabs<'a>() -> HashMap<String, Prefab> {
                 name: "random".to_string(),
                 kind: ValueKind::Raw(RawKind::Number),
             }],
+            stateful: true,
             ..Default::default()
         },
         Prefab {
@@ -1067,6 +1096,7 @@ pub fn get_prefabs<'a>() -> HashMap<String, Prefab> {
                     kind: ValueKind::Raw(RawKind::Vector),
                 },
             ],
+            stateful: true,
             ..Default::default()
         },
         Prefab {
@@ -1091,6 +1121,7 @@ pub fn get_prefabs<'a>() -> HashMap<String, Prefab> {
                 name: "direction".to_string(),
                 kind: ValueKind::Raw(RawKind::Vector),
             }],
+            stateful: true,
             ..Default::default()
         },
         Prefab {
@@ -1120,6 +1151,7 @@ pub fn get_prefabs<'a>() -> HashMap<String, Prefab> {
                     kind: ValueKind::Raw(RawKind::Object),
                 },
             ],
+            stateful: true,
             ..Default::default()
         },
         Prefab {
@@ -1199,6 +1231,7 @@ pub fn get_prefabs<'a>() -> HashMap<String, Prefab> {
                 kind: OptKind::Int8,
             }],
             callable: true,
+            terminal: true,
             ..Default::default()
         },
         Prefab {
@@ -1209,6 +1242,7 @@ pub fn get_prefabs<'a>() -> HashMap<String, Prefab> {
                 kind: OptKind::Int8,
             }],
             callable: true,
+            terminal: true,
             ..Default::default()
         },
         Prefab {
@@ -1318,6 +1352,7 @@ pub fn get_prefabs<'a>() -> HashMap<String, Prefab> {
                     kind: ValueKind::Raw(RawKind::Rotation),
                 },
             ],
+            stateful: true,
             ..Default::default()
         },
         Prefab {
@@ -1357,6 +1392,7 @@ pub fn get_prefabs<'a>() -> HashMap<String, Prefab> {
                     kind: ValueKind::Raw(RawKind::Vector),
                 },
             ],
+            stateful: true,
             ..Default::default()
         },
         Prefab {
@@ -1568,7 +1604,7 @@ pub fn get_prefabs<'a>() -> HashMap<String, Prefab> {
         },
         Prefab {
             name: "angular_limits".to_string(),
-            parts: array![[[0x60, 0x161]], [[0x162, 0x163]], [[0x164, 0x165]]],
+            parts: array![[[0x160, 0x161]], [[0x162, 0x163]], [[0x164, 0x165]]],
             inputs: vec![
                 ValuePort {
                     name: "constraint".to_string(),
@@ -2198,6 +2234,7 @@ pub fn get_prefabs<'a>() -> HashMap<String, Prefab> {
                     kind: ValueKind::Reference(RawKind::Number),
                 },
             ],
+            stateful: true,
             ..Default::default()
         },
         Prefab {
@@ -2246,6 +2283,7 @@ pub fn get_prefabs<'a>() -> HashMap<String, Prefab> {
                     kind: ValueKind::Raw(RawKind::Vector),
                 },
             ],
+            stateful: true,
             ..Default::default()
         },
         Prefab {
@@ -2566,6 +2604,7 @@ pub fn get_prefabs<'a>() -> HashMap<String, Prefab> {
                 name: "object".to_string(),
                 kind: ValueKind::Raw(RawKind::Object),
             }],
+            stateful: true,
             ..Default::default()
         },
         Prefab {
@@ -2580,6 +2619,7 @@ pub fn get_prefabs<'a>() -> HashMap<String, Prefab> {
                 name: "object".to_string(),
                 kind: ValueKind::Raw(RawKind::Object),
             }],
+            stateful: true,
             ..Default::default()
         },
         Prefab {
@@ -2594,6 +2634,7 @@ pub fn get_prefabs<'a>() -> HashMap<String, Prefab> {
                 name: "object".to_string(),
                 kind: ValueKind::Raw(RawKind::Object),
             }],
+            stateful: true,
             ..Default::default()
         },
         Prefab {
@@ -2608,6 +2649,7 @@ pub fn get_prefabs<'a>() -> HashMap<String, Prefab> {
                 name: "object".to_string(),
                 kind: ValueKind::Raw(RawKind::Object),
             }],
+            stateful: true,
             ..Default::default()
         },
         Prefab {
@@ -2622,6 +2664,7 @@ pub fn get_prefabs<'a>() -> HashMap<String, Prefab> {
                 name: "object".to_string(),
                 kind: ValueKind::Raw(RawKind::Object),
             }],
+            stateful: true,
             ..Default::default()
         },
         Prefab {
@@ -2636,6 +2679,7 @@ pub fn get_prefabs<'a>() -> HashMap<String, Prefab> {
                 name: "object".to_string(),
                 kind: ValueKind::Raw(RawKind::Object),
             }],
+            stateful: true,
             ..Default::default()
         },
         Prefab {
@@ -3000,6 +3044,10 @@ pub fn get_prefabs<'a>() -> HashMap<String, Prefab> {
         Prefab {
             name: "button".to_string(),
             parts: array![[[0x24C, 0x24D]], [[0x24E, 0x24F]]],
+            options: vec![Opt {
+                name: "label".to_string(),
+                kind: OptKind::Name,
+            }],
             callable: true,
             callbacks: vec![ExecutePort {
                 name: "button".to_string(),
@@ -0,0 +1,145 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+
+use crate::{
+    game::{Chunk, RawKind, ValueKind, WireKind},
+    transpiler::{
+        graph::{Endpoint, Graph, Node, NodeId},
+        prefabs::Prefab,
+    },
+};
+
+/// Reconstructs the graph of prefab instances placed in a level chunk from its blocks, opts and
+/// wires. Blocks that don't belong to a known prefab become nodes named after their id.
+pub fn raise_level(chunk: &Chunk, prefabs: &HashMap<String, Prefab>) -> Result<Graph> {
+    let mut graph = Graph::default();
+    let Some(blocks) = &chunk.blocks else {
+        return Ok(graph);
+    };
+
+    let by_id = prefabs
+        .values()
+        .filter(|prefab| !prefab.parts.is_empty())
+        .map(|prefab| (prefab.parts[[0, 0, 0]], prefab))
+        .collect::<HashMap<_, _>>();
+
+    // Cells of the level mapped to the node covering them.
+    let mut covered = HashMap::<[u16; 3], NodeId>::new();
+    let mut dims = HashMap::<NodeId, [usize; 3]>::new();
+
+    for ((z, y, x), &id) in blocks.indexed_iter() {
+        let position = [x as u16, y as u16, z as u16];
+        if id == 0 || covered.contains_key(&position) {
+            continue;
+        }
+
+        let (name, parts) = match by_id.get(&id) {
+            Some(prefab) => (prefab.name.clone(), prefab.parts.dim()),
+            None => (format!("block_{}", id), (1, 1, 1)),
+        };
+        let node = graph.add_node(Node::new(&name));
+        dims.insert(node, [parts.2, parts.1, parts.0]);
+
+        for (dz, dy, dx) in itertools::iproduct!(0..parts.0, 0..parts.1, 0..parts.2) {
+            covered.insert(
+                [position[0] + dx as u16, position[1] + dy as u16, position[2] + dz as u16],
+                node,
+            );
+        }
+    }
+
+    for opt in chunk.opts.iter().flatten() {
+        let Some(&node) = covered.get(&opt.position) else {
+            return Err(anyhow!("Can't find block for option at {:?}", opt.position));
+        };
+        graph.nodes[node].options.push((opt.index, opt.data.clone()));
+    }
+
+    for wire in chunk.wires.iter().flatten() {
+        let (Some(&from), Some(&to)) = (covered.get(&wire.from.position), covered.get(&wire.to.position)) else {
+            return Err(anyhow!(
+                "Can't find blocks for wire from {:?} to {:?}",
+                wire.from.position,
+                wire.to.position
+            ));
+        };
+        let from = (from, resolve_port(&graph, prefabs, &dims, from, wire.from.offset));
+        let to = (to, resolve_port(&graph, prefabs, &dims, to, wire.to.offset));
+
+        // Wires may be stored in either direction.
+        let (from, to) = match (&from.1, &to.1) {
+            (Side::Input(..), Side::Output(..)) => (to, from),
+            _ => (from, to),
+        };
+        let kind = match (&from.1, &to.1) {
+            (Side::Output(_, kind), _) | (_, Side::Input(_, kind)) => *kind,
+            _ => WireKind::Value(ValueKind::Raw(RawKind::Number)),
+        };
+        graph.connect(
+            kind,
+            Endpoint {
+                node: from.0,
+                port: from.1.port(),
+            },
+            Endpoint {
+                node: to.0,
+                port: to.1.port(),
+            },
+        );
+    }
+
+    Ok(graph)
+}
+
+/// Which side of a block a wire is attached to, along with the port and its kind.
+enum Side {
+    Input(usize, WireKind),
+    Output(usize, WireKind),
+}
+
+impl Side {
+    fn port(&self) -> usize {
+        match self {
+            Side::Input(port, _) | Side::Output(port, _) => *port,
+        }
+    }
+}
+
+fn resolve_port(
+    graph: &Graph,
+    prefabs: &HashMap<String, Prefab>,
+    dims: &HashMap<NodeId, [usize; 3]>,
+    node: NodeId,
+    offset: [u16; 3],
+) -> Side {
+    let [_, _, height] = dims[&node];
+    let prefab = prefabs.get(&graph.nodes[node].prefab);
+    // Rows are counted from the top of the block.
+    let row = (height as u16 * 8).saturating_sub(offset[2] + 1) as usize / 8;
+
+    if offset[0] == 0o03 && offset[2] == 0o06 + (height as u16 - 1) * 8 {
+        return Side::Input(0, WireKind::Execute);
+    }
+    if offset[0] == 0o03 && offset[2] == 0o00 {
+        return Side::Output(0, WireKind::Execute);
+    }
+    if offset[0] == 0o00 {
+        let kind = prefab
+            .and_then(|prefab| prefab.inputs.get(row))
+            .map_or(WireKind::Value(ValueKind::Raw(RawKind::Number)), |port| {
+                WireKind::Value(port.kind)
+            });
+        return Side::Input(row, kind);
+    }
+
+    let callbacks = prefab.map_or(0, |prefab| prefab.callbacks.len());
+    if row < callbacks {
+        return Side::Output(row + 1, WireKind::Execute);
+    }
+    let kind = prefab
+        .and_then(|prefab| prefab.outputs.get(row - callbacks))
+        .map_or(WireKind::Value(ValueKind::Raw(RawKind::Number)), |port| {
+            WireKind::Value(port.kind)
+        });
+    Side::Output(row - callbacks, kind)
+}